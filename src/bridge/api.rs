@@ -0,0 +1,471 @@
+use std::path::PathBuf;
+
+use log::{error, warn};
+use nvim_rs::Neovim;
+use rmpv::Value;
+
+use super::TxWrapper;
+use crate::window::{MonitorInfo, WINDOW_INFO};
+
+// Lua wrappers around the neovide.* rpc methods, available through require("neovide") once
+// neovide has attached
+const LUA_HELPERS: &str = r#"
+local channel = ...
+local function notify(name, ...) vim.rpcnotify(channel, "neovide." .. name, ...) end
+local function request(name) return vim.rpcrequest(channel, "neovide." .. name) end
+
+package.loaded["neovide"] = {
+  toggle_fullscreen = function() notify("toggle_fullscreen") end,
+  set_font_size = function(size) notify("set_font_size", size) end,
+  screenshot = function(path, options)
+    options = options or {}
+    local arguments = {}
+    if options.scale then table.insert(arguments, "++scale=" .. options.scale) end
+    if options.window then table.insert(arguments, "++window") end
+    if path then table.insert(arguments, vim.fn.fnamemodify(path, ":p")) end
+    notify("screenshot", vim.fn.getcwd(), vim.fn.getwininfo(vim.fn.win_getid())[1], arguments)
+  end,
+  record_start = function(path, options)
+    options = options or {}
+    local arguments = {}
+    if options.fps then table.insert(arguments, "++fps=" .. options.fps) end
+    if options.region then table.insert(arguments, "++region=" .. table.concat(options.region, ",")) end
+    if path then table.insert(arguments, vim.fn.fnamemodify(path, ":p")) end
+    notify("record_start", vim.fn.getcwd(), arguments)
+  end,
+  record_stop = function() notify("record_stop") end,
+  focus_window = function() notify("focus_window") end,
+  window_size = function() return request("window_size") end,
+  cell_size = function() return request("cell_size") end,
+  scale_factor = function() return request("scale_factor") end,
+  monitors = function() return request("monitors") end,
+}
+"#;
+
+// Where and how :NeovideScreenshot saves the frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreenshotRequest {
+    pub path: PathBuf,
+    pub scale: f32,
+    // The current neovim window, when only it is captured
+    pub window: Option<ScreenshotWindow>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScreenshotWindow {
+    // The winid, which is also the handle win_pos sends for the grid of the window
+    pub id: u64,
+    // Where the window is on the screen in cells as (column, row, width, height)
+    pub cells: (u64, u64, u64, u64),
+}
+
+// Where and how :NeovideRecordStart records
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreencastRequest {
+    pub path: PathBuf,
+    pub frame_rate: u32,
+    // The grid cells to record as (column, row, width, height), when not recording everything
+    pub region: Option<(u64, u64, u64, u64)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NeovideNotification {
+    ToggleFullscreen,
+    SetFontSize(f32),
+    Screenshot(ScreenshotRequest),
+    RecordStart(ScreencastRequest),
+    RecordStop,
+    FocusWindow,
+    // Neovim is about to exit on purpose, with the given exit code
+    Quit(i32),
+}
+
+fn parse_font_size(value: &Value) -> Option<f32> {
+    match value {
+        Value::F32(size) => Some(*size),
+        Value::F64(size) => Some(*size as f32),
+        Value::Integer(size) => size.as_f64().map(|size| size as f32),
+        Value::String(size) => size.as_str()?.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|size| *size > 0.0)
+}
+
+fn parse_window_info(window_info: &Value) -> Option<ScreenshotWindow> {
+    let field = |name: &str| {
+        window_info
+            .as_map()?
+            .iter()
+            .find(|(key, _)| key.as_str() == Some(name))?
+            .1
+            .as_u64()
+    };
+
+    // getwininfo() counts from 1
+    Some(ScreenshotWindow {
+        id: field("winid")?,
+        cells: (
+            field("wincol")?.saturating_sub(1),
+            field("winrow")?.saturating_sub(1),
+            field("width")?,
+            field("height")?,
+        ),
+    })
+}
+
+// The arguments are neovim's working directory, getwininfo() of the current window and the words
+// given to :NeovideScreenshot, which are ++scale={factor}, ++window and the path
+fn parse_screenshot(arguments: &[Value]) -> Option<ScreenshotRequest> {
+    let cwd = PathBuf::from(arguments.get(0)?.as_str()?);
+    let mut request = ScreenshotRequest {
+        path: cwd.clone(),
+        scale: 1.0,
+        window: None,
+    };
+
+    for word in arguments.get(2)?.as_array()? {
+        let word = word.as_str()?;
+        if word == "++window" {
+            request.window = Some(parse_window_info(arguments.get(1)?)?);
+        } else if let Some(scale) = word.strip_prefix("++scale=") {
+            request.scale = scale.parse().ok().filter(|scale: &f32| *scale > 0.0)?;
+        } else {
+            request.path = cwd.join(word);
+        }
+    }
+
+    Some(request)
+}
+
+fn parse_region(region: &str) -> Option<(u64, u64, u64, u64)> {
+    let numbers = region
+        .split(',')
+        .map(|number| number.trim().parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    match numbers[..] {
+        [column, row, width, height] if width > 0 && height > 0 => {
+            Some((column, row, width, height))
+        }
+        _ => None,
+    }
+}
+
+// The arguments are neovim's working directory and the words given to :NeovideRecordStart, which
+// are ++fps={rate}, ++region={column},{row},{width},{height} and the path
+fn parse_screencast(arguments: &[Value]) -> Option<ScreencastRequest> {
+    let cwd = PathBuf::from(arguments.get(0)?.as_str()?);
+    let mut request = ScreencastRequest {
+        path: cwd.clone(),
+        frame_rate: 30,
+        region: None,
+    };
+
+    for word in arguments.get(1)?.as_array()? {
+        let word = word.as_str()?;
+        if let Some(frame_rate) = word.strip_prefix("++fps=") {
+            request.frame_rate = frame_rate.parse().ok().filter(|rate| *rate > 0)?;
+        } else if let Some(region) = word.strip_prefix("++region=") {
+            request.region = Some(parse_region(region)?);
+        } else {
+            request.path = cwd.join(word);
+        }
+    }
+
+    Some(request)
+}
+
+impl NeovideNotification {
+    // Returns None for notifications outside of the neovide namespace, or with missing arguments
+    pub fn parse(event_name: &str, arguments: &[Value]) -> Option<NeovideNotification> {
+        let notification = match event_name {
+            "neovide.toggle_fullscreen" => NeovideNotification::ToggleFullscreen,
+            "neovide.set_font_size" => {
+                NeovideNotification::SetFontSize(parse_font_size(arguments.get(0)?)?)
+            }
+            "neovide.screenshot" => NeovideNotification::Screenshot(parse_screenshot(arguments)?),
+            "neovide.record_start" => {
+                NeovideNotification::RecordStart(parse_screencast(arguments)?)
+            }
+            "neovide.record_stop" => NeovideNotification::RecordStop,
+            "neovide.focus_window" => NeovideNotification::FocusWindow,
+            // v:exiting is only a number once neovim has decided how to exit
+            "neovide.quit" => NeovideNotification::Quit(
+                arguments.get(0).and_then(Value::as_i64).unwrap_or_default() as i32,
+            ),
+            _ => return None,
+        };
+        Some(notification)
+    }
+}
+
+// Sizes are written as h12 or h13.5. Other options may start with an h too, like the hist feature.
+fn is_size_option(part: &str) -> bool {
+    part.strip_prefix('h')
+        .map_or(false, |size| size.parse::<f32>().is_ok())
+}
+
+// Replaces the size in a guifont value, keeping the font list and other options
+pub fn guifont_with_size(guifont: &str, size: f32) -> String {
+    let mut parts = guifont.split(':');
+    let mut new_parts = vec![parts.next().unwrap_or_default().to_owned()];
+    new_parts.extend(
+        parts
+            .filter(|part| !part.is_empty() && !is_size_option(part))
+            .map(str::to_owned),
+    );
+    new_parts.push(format!("h{}", size));
+    new_parts.join(":")
+}
+
+pub async fn set_font_size(neovim: &Neovim<TxWrapper>, size: f32) {
+    let guifont = match neovim.get_option("guifont").await {
+        Ok(guifont) => guifont.as_str().unwrap_or_default().to_owned(),
+        Err(error) => {
+            error!("Could not read guifont: {}", error);
+            return;
+        }
+    };
+
+    let guifont = Value::from(guifont_with_size(&guifont, size));
+    if let Err(error) = neovim.set_option("guifont", guifont).await {
+        error!("Could not set guifont: {}", error);
+    }
+}
+
+fn size_value((width, height): (impl Into<Value>, impl Into<Value>)) -> Value {
+    Value::Map(vec![
+        (Value::from("width"), width.into()),
+        (Value::from("height"), height.into()),
+    ])
+}
+
+fn monitor_value(monitor: &MonitorInfo) -> Value {
+    let (x, y) = monitor.position;
+    Value::Map(vec![
+        (Value::from("name"), Value::from(monitor.name.as_str())),
+        (
+            Value::from("position"),
+            Value::Map(vec![
+                (Value::from("x"), Value::from(x)),
+                (Value::from("y"), Value::from(y)),
+            ]),
+        ),
+        (Value::from("size"), size_value(monitor.size)),
+        (
+            Value::from("scale_factor"),
+            Value::from(monitor.scale_factor),
+        ),
+    ])
+}
+
+// Answers rpcrequest calls in the neovide namespace with the state of the window
+pub fn handle_request(event_name: &str) -> Result<Value, Value> {
+    let window_info = WINDOW_INFO.read().clone();
+
+    match event_name {
+        "neovide.window_size" => Ok(size_value(window_info.size)),
+        "neovide.cell_size" => Ok(size_value(window_info.cell_size)),
+        "neovide.scale_factor" => Ok(Value::from(window_info.scale_factor)),
+        "neovide.monitors" => Ok(Value::Array(
+            window_info.monitors.iter().map(monitor_value).collect(),
+        )),
+        _ => Err(Value::from(format!("Unknown request {}", event_name))),
+    }
+}
+
+fn define_command(channel: u64, command: &str, attributes: &str, rpc_arguments: &str) -> String {
+    format!(
+        "command! {} {} call rpcnotify({}, {})",
+        attributes, command, channel, rpc_arguments
+    )
+}
+
+// Defines the :Neovide* commands and the Lua helpers for the given channel
+pub async fn setup_neovide_api(nvim: &Neovim<TxWrapper>, channel: u64, api_level: u64) {
+    let commands = [
+        define_command(
+            channel,
+            "NeovideToggleFullscreen",
+            "-nargs=0",
+            "'neovide.toggle_fullscreen'",
+        ),
+        define_command(
+            channel,
+            "NeovideFontSize",
+            "-nargs=1",
+            "'neovide.set_font_size', <q-args>",
+        ),
+        define_command(
+            channel,
+            "NeovideScreenshot",
+            "-nargs=* -complete=file",
+            concat!(
+                "'neovide.screenshot', getcwd(), getwininfo(win_getid())[0], ",
+                "map([<f-args>], {_, word -> word =~# '^++' ? word : fnamemodify(word, ':p')})"
+            ),
+        ),
+        define_command(
+            channel,
+            "NeovideRecordStart",
+            "-nargs=* -complete=file",
+            concat!(
+                "'neovide.record_start', getcwd(), ",
+                "map([<f-args>], {_, word -> word =~# '^++' ? word : fnamemodify(word, ':p')})"
+            ),
+        ),
+        define_command(
+            channel,
+            "NeovideRecordStop",
+            "-nargs=0",
+            "'neovide.record_stop'",
+        ),
+        define_command(
+            channel,
+            "NeovideFocus",
+            "-nargs=0",
+            "'neovide.focus_window'",
+        ),
+    ];
+
+    for command in commands.iter() {
+        if let Err(error) = nvim.command(command).await {
+            warn!("Could not define neovide command: {}", error);
+        }
+    }
+
+    // Tells a deliberate quit apart from a dropped connection, so that remote sessions close the
+    // window on :q instead of trying to reconnect
+    let quit_notification = format!(
+        concat!(
+            "augroup neovide_quit | autocmd! | ",
+            "autocmd VimLeavePre * call rpcnotify({}, 'neovide.quit', v:exiting) | ",
+            "augroup END"
+        ),
+        channel
+    );
+    if let Err(error) = nvim.command(&quit_notification).await {
+        warn!("Could not register the quit notification: {}", error);
+    }
+
+    // nvim_exec_lua was added in api level 7 (neovim 0.5)
+    if api_level >= 7 {
+        if let Err(error) = nvim.exec_lua(LUA_HELPERS, vec![Value::from(channel)]).await {
+            warn!("Could not define neovide lua helpers: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_size_replaces_only_the_height() {
+        assert_eq!(
+            guifont_with_size("Fira Code,Noto Emoji:h12:b", 14.0),
+            "Fira Code,Noto Emoji:b:h14"
+        );
+        assert_eq!(guifont_with_size("", 13.5), ":h13.5");
+        assert_eq!(
+            guifont_with_size("Fira Code:hist:+hlig:h12", 10.0),
+            "Fira Code:hist:+hlig:h10"
+        );
+    }
+
+    #[test]
+    fn notifications_are_parsed_with_their_arguments() {
+        assert_eq!(
+            NeovideNotification::parse("neovide.set_font_size", &[Value::from("16")]),
+            Some(NeovideNotification::SetFontSize(16.0))
+        );
+        assert_eq!(
+            NeovideNotification::parse("neovide.set_font_size", &[Value::from(12)]),
+            Some(NeovideNotification::SetFontSize(12.0))
+        );
+        assert_eq!(
+            NeovideNotification::parse("neovide.set_font_size", &[Value::from(-1)]),
+            None
+        );
+        assert_eq!(NeovideNotification::parse("neovide.screenshot", &[]), None);
+        assert_eq!(
+            NeovideNotification::parse(
+                "neovide.screenshot",
+                &[
+                    Value::from("/home/user"),
+                    Value::Map(vec![
+                        (Value::from("winid"), Value::from(1001)),
+                        (Value::from("wincol"), Value::from(41)),
+                        (Value::from("winrow"), Value::from(2)),
+                        (Value::from("width"), Value::from(40)),
+                        (Value::from("height"), Value::from(20)),
+                    ]),
+                    Value::from(vec![
+                        Value::from("++scale=2"),
+                        Value::from("++window"),
+                        Value::from("shots/split.png"),
+                    ]),
+                ]
+            ),
+            Some(NeovideNotification::Screenshot(ScreenshotRequest {
+                path: PathBuf::from("/home/user/shots/split.png"),
+                scale: 2.0,
+                window: Some(ScreenshotWindow {
+                    id: 1001,
+                    cells: (40, 1, 40, 20),
+                }),
+            }))
+        );
+        assert_eq!(
+            NeovideNotification::parse("neovide.quit", &[Value::from(3)]),
+            Some(NeovideNotification::Quit(3))
+        );
+        assert_eq!(
+            NeovideNotification::parse("neovide.quit", &[Value::Nil]),
+            Some(NeovideNotification::Quit(0))
+        );
+        assert_eq!(NeovideNotification::parse("redraw", &[]), None);
+    }
+
+    #[test]
+    fn recordings_are_parsed_with_their_options() {
+        let record_start = |words: &[&str]| {
+            NeovideNotification::parse(
+                "neovide.record_start",
+                &[
+                    Value::from("/home/user"),
+                    Value::from(
+                        words
+                            .iter()
+                            .map(|word| Value::from(*word))
+                            .collect::<Vec<_>>(),
+                    ),
+                ],
+            )
+        };
+
+        assert_eq!(
+            record_start(&["++fps=20", "++region=0,1,80,20", "cursor.gif"]),
+            Some(NeovideNotification::RecordStart(ScreencastRequest {
+                path: PathBuf::from("/home/user/cursor.gif"),
+                frame_rate: 20,
+                region: Some((0, 1, 80, 20)),
+            }))
+        );
+        assert_eq!(
+            record_start(&[]),
+            Some(NeovideNotification::RecordStart(ScreencastRequest {
+                path: PathBuf::from("/home/user"),
+                frame_rate: 30,
+                region: None,
+            }))
+        );
+        assert_eq!(record_start(&["++region=0,1,80"]), None);
+        assert_eq!(record_start(&["++fps=0"]), None);
+    }
+
+    #[test]
+    fn unknown_requests_are_errors() {
+        assert!(handle_request("neovide.scale_factor").is_ok());
+        assert!(handle_request("neovide.not_a_request").is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! What the connected neovim server supports, read from `nvim_get_api_info` instead of guessed
+//! from the version string. Used to pick the ui extensions to attach with, and handed to the
+//! editor so that features can fall back when the server is too old for them.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use nvim_rs::UiAttachOptions;
+use rmpv::Value;
+
+use super::events::{parse_array, parse_map, parse_string, parse_u64, ParseError, Result};
+
+pub const MINIMUM_SUPPORTED_VERSION: NeovimVersion = NeovimVersion {
+    major: 0,
+    minor: 4,
+    patch: 0,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NeovimVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl fmt::Display for NeovimVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UiExtensions {
+    pub multigrid: bool,
+    pub cmdline: bool,
+    pub messages: bool,
+    pub popupmenu: bool,
+    pub tabline: bool,
+}
+
+impl UiExtensions {
+    pub fn attach_options(&self) -> UiAttachOptions {
+        let mut options = UiAttachOptions::new();
+        options.set_rgb(true);
+        options.set_linegrid_external(true);
+        options.set_multigrid_external(self.multigrid);
+        options.set_cmdline_external(self.cmdline);
+        options.set_messages_external(self.messages);
+        options.set_popupmenu_external(self.popupmenu);
+        options.set_tabline_external(self.tabline);
+        options
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NeovimCapabilities {
+    pub version: NeovimVersion,
+    pub api_level: u64,
+    pub ui_events: HashSet<String>,
+    pub ui_options: HashSet<String>,
+}
+
+impl NeovimCapabilities {
+    pub fn is_supported(&self) -> bool {
+        self.version >= MINIMUM_SUPPORTED_VERSION
+    }
+
+    pub fn supports_ui_event(&self, event_name: &str) -> bool {
+        self.ui_events.contains(event_name)
+    }
+
+    pub fn supports_ui_option(&self, option_name: &str) -> bool {
+        self.ui_options.contains(option_name)
+    }
+
+    // Drops every requested extension the server does not know about. Asking for one anyway would
+    // make nvim_ui_attach fail outright.
+    pub fn negotiate(&self, requested: UiExtensions) -> UiExtensions {
+        UiExtensions {
+            multigrid: requested.multigrid && self.supports_ui_option("ext_multigrid"),
+            cmdline: requested.cmdline && self.supports_ui_option("ext_cmdline"),
+            messages: requested.messages && self.supports_ui_option("ext_messages"),
+            popupmenu: requested.popupmenu && self.supports_ui_option("ext_popupmenu"),
+            tabline: requested.tabline && self.supports_ui_option("ext_tabline"),
+        }
+    }
+}
+
+fn parse_version(version_value: Value) -> Result<(NeovimVersion, u64)> {
+    let mut version = NeovimVersion::default();
+    let mut api_level = 0;
+
+    for (name, value) in parse_map(version_value)? {
+        match (parse_string(name)?.as_str(), value) {
+            ("major", major) => version.major = parse_u64(major)?,
+            ("minor", minor) => version.minor = parse_u64(minor)?,
+            ("patch", patch) => version.patch = parse_u64(patch)?,
+            ("api_level", level) => api_level = parse_u64(level)?,
+            _ => {}
+        }
+    }
+
+    Ok((version, api_level))
+}
+
+fn parse_ui_event_name(ui_event_value: Value) -> Result<String> {
+    for (name, value) in parse_map(ui_event_value)? {
+        if parse_string(name)? == "name" {
+            return parse_string(value);
+        }
+    }
+
+    Err(ParseError::Format("ui event without a name".to_owned()))
+}
+
+// The api info is a [channel_id, metadata] pair
+pub fn parse_api_info(api_info: Vec<Value>) -> Result<NeovimCapabilities> {
+    let metadata = api_info
+        .into_iter()
+        .nth(1)
+        .ok_or_else(|| ParseError::Format("api info without metadata".to_owned()))?;
+
+    let mut capabilities = NeovimCapabilities::default();
+
+    for (name, value) in parse_map(metadata)? {
+        match (parse_string(name)?.as_str(), value) {
+            ("version", version) => {
+                let (version, api_level) = parse_version(version)?;
+                capabilities.version = version;
+                capabilities.api_level = api_level;
+            }
+            ("ui_events", ui_events) => {
+                capabilities.ui_events = parse_array(ui_events)?
+                    .into_iter()
+                    .map(parse_ui_event_name)
+                    .collect::<Result<_>>()?;
+            }
+            ("ui_options", ui_options) => {
+                capabilities.ui_options = parse_array(ui_options)?
+                    .into_iter()
+                    .map(parse_string)
+                    .collect::<Result<_>>()?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(capabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_info(major: u64, minor: u64, ui_options: &[&str]) -> Vec<Value> {
+        let version = Value::Map(vec![
+            (Value::from("major"), Value::from(major)),
+            (Value::from("minor"), Value::from(minor)),
+            (Value::from("patch"), Value::from(2)),
+            (Value::from("api_level"), Value::from(7)),
+            (Value::from("prerelease"), Value::from(false)),
+        ]);
+        let ui_events = Value::Array(vec![Value::Map(vec![
+            (Value::from("name"), Value::from("grid_line")),
+            (Value::from("since"), Value::from(5)),
+        ])]);
+        let ui_options = Value::Array(ui_options.iter().map(|&name| Value::from(name)).collect());
+
+        vec![
+            Value::from(1),
+            Value::Map(vec![
+                (Value::from("version"), version),
+                (Value::from("functions"), Value::Array(vec![])),
+                (Value::from("ui_events"), ui_events),
+                (Value::from("ui_options"), ui_options),
+            ]),
+        ]
+    }
+
+    #[test]
+    fn parses_api_info() {
+        let capabilities = parse_api_info(api_info(0, 10, &["rgb", "ext_cmdline"])).unwrap();
+
+        assert_eq!(
+            capabilities.version,
+            NeovimVersion {
+                major: 0,
+                minor: 10,
+                patch: 2
+            }
+        );
+        assert_eq!(capabilities.api_level, 7);
+        assert!(capabilities.supports_ui_event("grid_line"));
+        assert!(capabilities.supports_ui_option("ext_cmdline"));
+        assert!(!capabilities.supports_ui_option("ext_messages"));
+    }
+
+    #[test]
+    fn versions_from_0_4_are_supported() {
+        assert!(!parse_api_info(api_info(0, 3, &[])).unwrap().is_supported());
+        assert!(parse_api_info(api_info(0, 4, &[])).unwrap().is_supported());
+        assert!(parse_api_info(api_info(0, 10, &[])).unwrap().is_supported());
+        assert!(parse_api_info(api_info(1, 0, &[])).unwrap().is_supported());
+    }
+
+    #[test]
+    fn negotiation_drops_unsupported_extensions() {
+        let capabilities =
+            parse_api_info(api_info(0, 4, &["ext_multigrid", "ext_popupmenu"])).unwrap();
+        let requested = UiExtensions {
+            multigrid: true,
+            cmdline: true,
+            messages: false,
+            popupmenu: true,
+            tabline: true,
+        };
+
+        assert_eq!(
+            capabilities.negotiate(requested),
+            UiExtensions {
+                multigrid: true,
+                popupmenu: true,
+                ..UiExtensions::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+#[cfg(target_os = "linux")]
+use copypasta::x11_clipboard::{Clipboard as X11Clipboard, Primary, X11ClipboardContext};
+#[cfg(not(target_os = "linux"))]
+use copypasta::ClipboardContext;
+use copypasta::ClipboardProvider;
+use log::warn;
+use nvim_rs::Neovim;
+use rmpv::Value;
+
+use super::TxWrapper;
+
+type ProviderResult<T> = Result<T, Box<dyn Error + Send + Sync + 'static>>;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ClipboardKind {
+    // The + register
+    Clipboard,
+    // The * register. Only X11 has a separate primary selection, elsewhere it is the clipboard.
+    Primary,
+}
+
+impl ClipboardKind {
+    fn parse(register: &Value) -> Option<ClipboardKind> {
+        match register.as_str()? {
+            "+" => Some(ClipboardKind::Clipboard),
+            "*" => Some(ClipboardKind::Primary),
+            _ => None,
+        }
+    }
+}
+
+pub trait ClipboardBackend: Send {
+    fn get_contents(&mut self, kind: ClipboardKind) -> Result<String, String>;
+    fn set_contents(&mut self, kind: ClipboardKind, contents: String) -> Result<(), String>;
+}
+
+// The clipboard of the machine neovide runs on, which is not necessarily the one neovim runs on
+#[derive(Default)]
+pub struct SystemClipboard {
+    // X11 selections are served by their owner, so the contexts are kept for as long as neovide
+    // runs
+    #[cfg(target_os = "linux")]
+    clipboard: Option<X11ClipboardContext<X11Clipboard>>,
+    #[cfg(target_os = "linux")]
+    primary: Option<X11ClipboardContext<Primary>>,
+}
+
+impl SystemClipboard {
+    #[cfg(target_os = "linux")]
+    fn with_provider<T>(
+        &mut self,
+        kind: ClipboardKind,
+        action: impl FnOnce(&mut dyn ClipboardProvider) -> ProviderResult<T>,
+    ) -> ProviderResult<T> {
+        match kind {
+            ClipboardKind::Clipboard => {
+                if self.clipboard.is_none() {
+                    self.clipboard = Some(X11ClipboardContext::new()?);
+                }
+                action(self.clipboard.as_mut().unwrap())
+            }
+            ClipboardKind::Primary => {
+                if self.primary.is_none() {
+                    self.primary = Some(X11ClipboardContext::new()?);
+                }
+                action(self.primary.as_mut().unwrap())
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn with_provider<T>(
+        &mut self,
+        _kind: ClipboardKind,
+        action: impl FnOnce(&mut dyn ClipboardProvider) -> ProviderResult<T>,
+    ) -> ProviderResult<T> {
+        let mut context = ClipboardContext::new()?;
+        action(&mut context)
+    }
+}
+
+impl ClipboardBackend for SystemClipboard {
+    fn get_contents(&mut self, kind: ClipboardKind) -> Result<String, String> {
+        self.with_provider(kind, |provider| provider.get_contents())
+            .map_err(|error| error.to_string())
+    }
+
+    fn set_contents(&mut self, kind: ClipboardKind, contents: String) -> Result<(), String> {
+        self.with_provider(kind, |provider| provider.set_contents(contents))
+            .map_err(|error| error.to_string())
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryClipboard {
+    contents: HashMap<ClipboardKind, String>,
+}
+
+#[cfg(test)]
+impl ClipboardBackend for MemoryClipboard {
+    fn get_contents(&mut self, kind: ClipboardKind) -> Result<String, String> {
+        Ok(self.contents.get(&kind).cloned().unwrap_or_default())
+    }
+
+    fn set_contents(&mut self, kind: ClipboardKind, contents: String) -> Result<(), String> {
+        self.contents.insert(kind, contents);
+        Ok(())
+    }
+}
+
+// Serves the copy and paste requests of the g:clipboard provider registered by neovide
+pub struct Clipboard {
+    backend: Box<dyn ClipboardBackend>,
+    // The system clipboard only holds text, so the register type of our own last copy is
+    // remembered to paste it back blockwise or linewise
+    last_copies: HashMap<ClipboardKind, (String, String)>,
+}
+
+impl Clipboard {
+    pub fn new(backend: Box<dyn ClipboardBackend>) -> Clipboard {
+        Clipboard {
+            backend,
+            last_copies: HashMap::new(),
+        }
+    }
+
+    fn copy(
+        &mut self,
+        kind: ClipboardKind,
+        lines: Vec<String>,
+        register_type: String,
+    ) -> Result<(), String> {
+        let mut contents = lines.join("\n");
+        if register_type == "V" {
+            contents.push('\n');
+        }
+
+        self.backend.set_contents(kind, contents.clone())?;
+        self.last_copies.insert(kind, (contents, register_type));
+        Ok(())
+    }
+
+    fn paste(&mut self, kind: ClipboardKind) -> Result<(Vec<String>, String), String> {
+        let contents = self.backend.get_contents(kind)?;
+        let mut lines: Vec<String> = contents
+            .split('\n')
+            .map(|line| line.trim_end_matches('\r').to_owned())
+            .collect();
+
+        // An empty type lets neovim decide from the trailing newline
+        let register_type = match self.last_copies.get(&kind) {
+            Some((last_contents, register_type)) if *last_contents == contents => {
+                register_type.clone()
+            }
+            _ => String::new(),
+        };
+
+        // Linewise registers end in a newline which isn't a line of its own
+        if register_type == "V" && lines.len() > 1 {
+            lines.pop();
+        }
+
+        Ok((lines, register_type))
+    }
+
+    // Returns None for requests that aren't meant for the clipboard
+    pub fn handle_request(
+        &mut self,
+        event_name: &str,
+        arguments: Vec<Value>,
+    ) -> Option<Result<Value, Value>> {
+        let response = match event_name {
+            "neovide.clipboard_get" => {
+                let kind = arguments.get(0).and_then(ClipboardKind::parse)?;
+                self.paste(kind).map(|(lines, register_type)| {
+                    Value::from(vec![
+                        Value::from(lines.into_iter().map(Value::from).collect::<Vec<_>>()),
+                        Value::from(register_type),
+                    ])
+                })
+            }
+            "neovide.clipboard_set" => {
+                let mut arguments = arguments.into_iter();
+                let kind = arguments.next().as_ref().and_then(ClipboardKind::parse)?;
+                let lines = match arguments.next() {
+                    Some(Value::Array(lines)) => lines
+                        .into_iter()
+                        .map(|line| line.as_str().unwrap_or_default().to_owned())
+                        .collect(),
+                    _ => return Some(Err(Value::from("Expected a list of lines"))),
+                };
+                let register_type = arguments
+                    .next()
+                    .and_then(|register_type| register_type.as_str().map(str::to_owned))
+                    .unwrap_or_default();
+                self.copy(kind, lines, register_type).map(|_| Value::Nil)
+            }
+            _ => return None,
+        };
+
+        Some(response.map_err(|error| {
+            warn!("Clipboard request {} failed: {}", event_name, error);
+            Value::from(error)
+        }))
+    }
+}
+
+// Makes neovim use neovide's clipboard unless the user configured a different provider. Over a
+// remote connection this is the clipboard of the machine neovide runs on.
+pub async fn register_clipboard_provider(nvim: &Neovim<TxWrapper>, channel: u64) {
+    // The clipboard is only reached through X11 on linux, so wayland sessions keep whichever
+    // provider neovim finds on its own, such as wl-copy
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        log::info!("Not registering the clipboard provider on wayland");
+        return;
+    }
+
+    let copy = |register| {
+        format!(
+            "'{0}': {{lines, regtype -> rpcrequest({1}, 'neovide.clipboard_set', '{0}', lines, regtype)}}",
+            register, channel
+        )
+    };
+    let paste = |register| {
+        format!(
+            "'{0}': {{-> rpcrequest({1}, 'neovide.clipboard_get', '{0}')}}",
+            register, channel
+        )
+    };
+    let provider = format!(
+        "{{'name': 'neovide', 'copy': {{{}, {}}}, 'paste': {{{}, {}}}, 'cache_enabled': 0}}",
+        copy("+"),
+        copy("*"),
+        paste("+"),
+        paste("*")
+    );
+
+    // A provider left behind by an earlier connection to the same server is replaced, since the
+    // channel has changed
+    let command = format!(
+        concat!(
+            "if !exists('g:clipboard') || get(g:clipboard, 'name', '') ==# 'neovide' | ",
+            "let g:clipboard = {} | ",
+            "unlet! g:loaded_clipboard_provider | ",
+            "runtime autoload/provider/clipboard.vim | ",
+            "endif"
+        ),
+        provider
+    );
+
+    // An embedded neovim only reads the user's config once the ui attaches, so the provider is
+    // registered on VimEnter to let a g:clipboard from the config win. Servers which are already
+    // running have read theirs.
+    let already_entered = nvim
+        .eval("v:vim_did_enter")
+        .await
+        .ok()
+        .and_then(|value| value.as_i64())
+        .unwrap_or(0)
+        != 0;
+    let command = if already_entered {
+        command
+    } else {
+        format!("autocmd VimEnter * ++once {}", command)
+    };
+
+    if let Err(error) = nvim.command(&command).await {
+        warn!("Could not register the clipboard provider: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(clipboard: &mut Clipboard, event_name: &str, arguments: Vec<Value>) -> Value {
+        clipboard
+            .handle_request(event_name, arguments)
+            .unwrap()
+            .unwrap()
+    }
+
+    fn lines(lines: &[&str]) -> Value {
+        Value::from(
+            lines
+                .iter()
+                .map(|line| Value::from(*line))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn linewise_copies_paste_back_linewise() {
+        let mut clipboard = Clipboard::new(Box::new(MemoryClipboard::default()));
+        request(
+            &mut clipboard,
+            "neovide.clipboard_set",
+            vec![Value::from("+"), lines(&["one", "two"]), Value::from("V")],
+        );
+
+        let pasted = request(
+            &mut clipboard,
+            "neovide.clipboard_get",
+            vec![Value::from("+")],
+        );
+        assert_eq!(
+            pasted,
+            Value::from(vec![lines(&["one", "two"]), Value::from("V")])
+        );
+
+        // The primary selection is separate
+        let pasted = request(
+            &mut clipboard,
+            "neovide.clipboard_get",
+            vec![Value::from("*")],
+        );
+        assert_eq!(pasted, Value::from(vec![lines(&[""]), Value::from("")]));
+    }
+
+    #[test]
+    fn outside_contents_let_neovim_pick_the_type() {
+        let mut backend = MemoryClipboard::default();
+        backend
+            .set_contents(ClipboardKind::Clipboard, "a\r\nb".to_owned())
+            .unwrap();
+        let mut clipboard = Clipboard::new(Box::new(backend));
+
+        let pasted = request(
+            &mut clipboard,
+            "neovide.clipboard_get",
+            vec![Value::from("+")],
+        );
+        assert_eq!(
+            pasted,
+            Value::from(vec![lines(&["a", "b"]), Value::from("")])
+        );
+    }
+
+    #[test]
+    fn other_requests_are_not_handled() {
+        let mut clipboard = Clipboard::new(Box::new(MemoryClipboard::default()));
+        assert!(clipboard
+            .handle_request("neovide.window_size", Vec::new())
+            .is_none());
+        assert!(clipboard
+            .handle_request("neovide.clipboard_get", vec![Value::from("a")])
+            .is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::Path;
+use std::process::Command;
+
+use log::warn;
+
+// Splits a command template into words the way a shell would, honoring single quotes, double
+// quotes and backslash escapes. Nothing is expanded.
+pub fn split_command_template(template: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut characters = template.chars();
+    let mut quote = None;
+
+    while let Some(character) = characters.next() {
+        match (quote, character) {
+            (Some(open), character) if character == open => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(escaped) = characters.next() {
+                    word.get_or_insert_with(String::new).push(escaped);
+                }
+            }
+            (Some(_), character) => word.get_or_insert_with(String::new).push(character),
+            (None, '\'') | (None, '"') => {
+                quote = Some(character);
+                word.get_or_insert_with(String::new);
+            }
+            (None, character) if character.is_whitespace() => words.extend(word.take()),
+            (None, character) => word.get_or_insert_with(String::new).push(character),
+        }
+    }
+    words.extend(word);
+
+    words
+}
+
+// Quotes an argument for a POSIX shell
+pub fn shell_quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', "'\\''"))
+}
+
+// Builds the command which runs neovim through the given prefix, such as "ssh host" or
+// "docker exec -i container". Neovim is started from a login shell so that it gets the PATH of
+// the environment it runs in, and in the same directory as neovide when that exists there.
+pub fn build_wrapped_command(
+    template: &str,
+    neovim_bin: Option<&str>,
+    neovim_arguments: &[String],
+) -> Option<Command> {
+    let mut words = split_command_template(template);
+    if words.is_empty() {
+        warn!("The neovim command is empty, starting neovim directly");
+        return None;
+    }
+    let program = words.remove(0);
+
+    let mut script = String::new();
+    if let Some(cwd) = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| cwd.to_str().map(str::to_owned))
+    {
+        script.push_str(&format!("cd {} 2>/dev/null; ", shell_quote(&cwd)));
+    }
+    script.push_str("exec ");
+    script.push_str(&shell_quote(neovim_bin.unwrap_or("nvim")));
+    for argument in neovim_arguments {
+        script.push(' ');
+        script.push_str(&shell_quote(argument));
+    }
+
+    // Ssh joins its arguments into one string for the remote shell to split again, so the
+    // script needs another level of quoting to arrive in one piece
+    let is_ssh = Path::new(&program)
+        .file_stem()
+        .map(|stem| stem == "ssh")
+        .unwrap_or(false);
+    if is_ssh {
+        script = shell_quote(&script);
+    }
+
+    let mut cmd = Command::new(program);
+    cmd.args(words).args(&["sh", "-lc", &script]);
+    Some(cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_split_like_a_shell() {
+        assert_eq!(
+            split_command_template("docker exec -i  'my container'"),
+            vec!["docker", "exec", "-i", "my container"]
+        );
+        assert_eq!(
+            split_command_template(r#"ssh -o "ProxyJump a\"b" host\ name ''"#),
+            vec!["ssh", "-o", "ProxyJump a\"b", "host name", ""]
+        );
+        assert!(split_command_template("   ").is_empty());
+    }
+
+    #[test]
+    fn quoting_survives_single_quotes() {
+        assert_eq!(shell_quote("it's"), r#"'it'\''s'"#);
+    }
+}
@@ -3,17 +3,21 @@
 
 use std::{
     io::{self, Error, ErrorKind},
+    path::Path,
     process::Stdio,
 };
 
 use tokio::{
     io::split,
     net::{TcpStream, ToSocketAddrs},
-    process::Command,
+    process::{Child, Command},
     spawn,
     task::JoinHandle,
 };
 
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
 use nvim_rs::compat::tokio::TokioAsyncReadCompatExt;
 use nvim_rs::{error::LoopError, neovim::Neovim, Handler};
 
@@ -36,13 +40,54 @@ where
     Ok((neovim, io_handle))
 }
 
+/// Connect to a neovim instance via a unix domain socket, such as one created with
+/// `nvim --listen /tmp/nvim.sock`
+#[cfg(unix)]
+pub async fn new_unix_socket<P, H>(
+    path: P,
+    handler: H,
+) -> io::Result<(Neovim<TxWrapper>, JoinHandle<Result<(), Box<LoopError>>>)>
+where
+    P: AsRef<Path>,
+    H: Handler<Writer = TxWrapper>,
+{
+    let stream = UnixStream::connect(path).await?;
+    let (reader, writer) = split(stream);
+    let (neovim, io) = Neovim::<TxWrapper>::new(reader.compat_read(), writer.wrap_tx(), handler);
+    let io_handle = spawn(io);
+
+    Ok((neovim, io_handle))
+}
+
+/// Named pipes are not available with the version of tokio we depend on, so connecting to a
+/// local server is only supported on unix for now
+#[cfg(not(unix))]
+pub async fn new_unix_socket<P, H>(
+    _path: P,
+    _handler: H,
+) -> io::Result<(Neovim<TxWrapper>, JoinHandle<Result<(), Box<LoopError>>>)>
+where
+    P: AsRef<Path>,
+    H: Handler<Writer = TxWrapper>,
+{
+    Err(Error::new(
+        ErrorKind::Other,
+        "Connecting to a local neovim server is not supported on this platform",
+    ))
+}
+
 /// Connect to a neovim instance by spawning a new one
 ///
-/// stdin/stdout will be rewritten to `Stdio::piped()`
+/// stdin/stdout will be rewritten to `Stdio::piped()`. The child is returned so that its stderr
+/// can be read and it can be stopped again.
 pub async fn new_child_cmd<H>(
     cmd: &mut Command,
     handler: H,
-) -> io::Result<(Neovim<TxWrapper>, JoinHandle<Result<(), Box<LoopError>>>)>
+) -> io::Result<(
+    Neovim<TxWrapper>,
+    JoinHandle<Result<(), Box<LoopError>>>,
+    Child,
+)>
 where
     H: Handler<Writer = TxWrapper>,
 {
@@ -61,5 +106,5 @@ where
     let (neovim, io) = Neovim::<TxWrapper>::new(stdout, stdin, handler);
     let io_handle = spawn(io);
 
-    Ok((neovim, io_handle))
+    Ok((neovim, io_handle, child))
 }
@@ -19,8 +19,9 @@ pub enum ParseError {
     Bool(Value),
     WindowAnchor(Value),
     Format(String),
+    UnknownEvent,
 }
-type Result<T> = std::result::Result<T, ParseError>;
+pub(super) type Result<T> = std::result::Result<T, ParseError>;
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -38,6 +39,7 @@ impl fmt::Display for ParseError {
             ParseError::Format(debug_text) => {
                 write!(f, "invalid event format {}", debug_text)
             }
+            ParseError::UnknownEvent => write!(f, "unknown event"),
         }
     }
 }
@@ -48,6 +50,18 @@ impl error::Error for ParseError {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct RedrawEventError {
+    pub event_name: String,
+    pub error: ParseError,
+}
+
+impl fmt::Display for RedrawEventError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.event_name, self.error)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct GridLineCell {
     pub text: String,
@@ -57,7 +71,22 @@ pub struct GridLineCell {
 
 pub type StyledContent = Vec<(u64, String)>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TabInfo {
+    // The tabpage handle exactly as neovim sent it, so that it can be passed back in api calls
+    pub tab: Value,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PopupMenuItem {
+    pub word: String,
+    pub kind: String,
+    pub menu: String,
+    pub info: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MessageKind {
     Unknown,
     Confirm,
@@ -192,6 +221,7 @@ pub enum RedrawEvent {
     },
     WindowPosition {
         grid: u64,
+        window: u64,
         start_row: u64,
         start_column: u64,
         width: u64,
@@ -199,6 +229,7 @@ pub enum RedrawEvent {
     },
     WindowFloatPosition {
         grid: u64,
+        window: u64,
         anchor: WindowAnchor,
         anchor_grid: u64,
         anchor_row: f64,
@@ -271,6 +302,26 @@ pub enum RedrawEvent {
     MessageHistoryShow {
         entries: Vec<(MessageKind, StyledContent)>,
     },
+    HighlightGroupSet {
+        name: String,
+        id: u64,
+    },
+    PopupMenuShow {
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        row: u64,
+        column: u64,
+        // None when the menu completes the command line
+        grid: Option<u64>,
+    },
+    PopupMenuSelect {
+        selected: Option<u64>,
+    },
+    PopupMenuHide,
+    TablineUpdate {
+        current_tab: Value,
+        tabs: Vec<TabInfo>,
+    },
 }
 
 #[derive(Debug)]
@@ -356,13 +407,15 @@ fn unpack_color(packed_color: u64) -> Color4f {
     }
 }
 
+// Newer versions of neovim append fields to existing events, so any values past the ones we know
+// about are ignored rather than treated as an error.
 fn extract_values<Arr: AsMut<[Value]>>(values: Vec<Value>, mut arr: Arr) -> Result<Arr> {
     let arr_ref = arr.as_mut();
 
-    if values.len() != arr_ref.len() {
+    if values.len() < arr_ref.len() {
         Err(ParseError::Format(format!("{:?}", values)))
     } else {
-        for (i, val) in values.into_iter().enumerate() {
+        for (i, val) in values.into_iter().take(arr_ref.len()).enumerate() {
             arr_ref[i] = val;
         }
 
@@ -370,19 +423,19 @@ fn extract_values<Arr: AsMut<[Value]>>(values: Vec<Value>, mut arr: Arr) -> Resu
     }
 }
 
-fn parse_array(array_value: Value) -> Result<Vec<Value>> {
+pub(super) fn parse_array(array_value: Value) -> Result<Vec<Value>> {
     array_value.try_into().map_err(ParseError::Array)
 }
 
-fn parse_map(map_value: Value) -> Result<Vec<(Value, Value)>> {
+pub(super) fn parse_map(map_value: Value) -> Result<Vec<(Value, Value)>> {
     map_value.try_into().map_err(ParseError::Map)
 }
 
-fn parse_string(string_value: Value) -> Result<String> {
+pub(super) fn parse_string(string_value: Value) -> Result<String> {
     string_value.try_into().map_err(ParseError::String)
 }
 
-fn parse_u64(u64_value: Value) -> Result<u64> {
+pub(super) fn parse_u64(u64_value: Value) -> Result<u64> {
     u64_value.try_into().map_err(ParseError::U64)
 }
 
@@ -394,6 +447,19 @@ fn parse_f64(f64_value: Value) -> Result<f64> {
     f64_value.try_into().map_err(ParseError::F64)
 }
 
+// Window handles arrive as msgpack extensions wrapping the same number getwininfo() reports as
+// winid
+fn parse_window_handle(window_value: Value) -> Result<u64> {
+    let handle = match &window_value {
+        Value::Ext(_, data) => rmpv::decode::read_value(&mut data.as_slice())
+            .ok()
+            .and_then(|handle| handle.as_u64()),
+        window_value => window_value.as_u64(),
+    };
+
+    handle.ok_or(ParseError::U64(window_value))
+}
+
 fn parse_bool(bool_value: Value) -> Result<bool> {
     bool_value.try_into().map_err(ParseError::Bool)
 }
@@ -663,11 +729,12 @@ fn parse_win_pos(win_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
         Value::Nil,
         Value::Nil,
     ];
-    let [grid, _window, start_row, start_column, width, height] =
+    let [grid, window, start_row, start_column, width, height] =
         extract_values(win_pos_arguments, values)?;
 
     Ok(RedrawEvent::WindowPosition {
         grid: parse_u64(grid)?,
+        window: parse_window_handle(window)?,
         start_row: parse_u64(start_row)?,
         start_column: parse_u64(start_column)?,
         width: parse_u64(width)?,
@@ -687,7 +754,7 @@ fn parse_window_anchor(value: Value) -> Result<WindowAnchor> {
 }
 
 fn parse_win_float_pos(win_float_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
-    if win_float_pos_arguments.len() == 8 {
+    if win_float_pos_arguments.len() >= 8 {
         let values = [
             Value::Nil,
             Value::Nil,
@@ -699,11 +766,12 @@ fn parse_win_float_pos(win_float_pos_arguments: Vec<Value>) -> Result<RedrawEven
             Value::Nil,
         ];
 
-        let [grid, _window, anchor, anchor_grid, anchor_row, anchor_column, focusable, sort_order] =
+        let [grid, window, anchor, anchor_grid, anchor_row, anchor_column, focusable, sort_order] =
             extract_values(win_float_pos_arguments, values)?;
 
         Ok(RedrawEvent::WindowFloatPosition {
             grid: parse_u64(grid)?,
+            window: parse_window_handle(window)?,
             anchor: parse_window_anchor(anchor)?,
             anchor_grid: parse_u64(anchor_grid)?,
             anchor_row: parse_f64(anchor_row)?,
@@ -722,11 +790,12 @@ fn parse_win_float_pos(win_float_pos_arguments: Vec<Value>) -> Result<RedrawEven
             Value::Nil,
         ];
 
-        let [grid, _window, anchor, anchor_grid, anchor_row, anchor_column, focusable] =
+        let [grid, window, anchor, anchor_grid, anchor_row, anchor_column, focusable] =
             extract_values(win_float_pos_arguments, values)?;
 
         Ok(RedrawEvent::WindowFloatPosition {
             grid: parse_u64(grid)?,
+            window: parse_window_handle(window)?,
             anchor: parse_window_anchor(anchor)?,
             anchor_grid: parse_u64(anchor_grid)?,
             anchor_row: parse_f64(anchor_row)?,
@@ -923,66 +992,182 @@ fn parse_msg_history_show(msg_history_show_arguments: Vec<Value>) -> Result<Redr
     })
 }
 
-pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
-    let mut event_contents = parse_array(event_value)?.into_iter();
-    let event_name = event_contents
-        .next()
-        .ok_or_else(|| ParseError::Format(format!("{:?}", event_contents)))
-        .and_then(parse_string)?;
+fn parse_hl_group_set(hl_group_set_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [name, id] = extract_values(hl_group_set_arguments, [Value::Nil, Value::Nil])?;
+
+    Ok(RedrawEvent::HighlightGroupSet {
+        name: parse_string(name)?,
+        id: parse_u64(id)?,
+    })
+}
+
+fn parse_popupmenu_item(item: Value) -> Result<PopupMenuItem> {
+    let values = [Value::Nil, Value::Nil, Value::Nil, Value::Nil];
+    let [word, kind, menu, info] = extract_values(parse_array(item)?, values)?;
+
+    Ok(PopupMenuItem {
+        word: parse_string(word)?,
+        kind: parse_string(kind)?,
+        menu: parse_string(menu)?,
+        info: parse_string(info)?,
+    })
+}
+
+// Neovim uses -1 for "no item selected" and for the command line grid
+fn parse_optional_index(index: Value) -> Result<Option<u64>> {
+    let index = parse_i64(index)?;
+    Ok(if index < 0 { None } else { Some(index as u64) })
+}
+
+fn parse_popupmenu_show(popupmenu_show_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let values = [Value::Nil, Value::Nil, Value::Nil, Value::Nil, Value::Nil];
+    let [items, selected, row, column, grid] = extract_values(popupmenu_show_arguments, values)?;
+
+    Ok(RedrawEvent::PopupMenuShow {
+        items: parse_array(items)?
+            .into_iter()
+            .map(parse_popupmenu_item)
+            .collect::<Result<_>>()?,
+        selected: parse_optional_index(selected)?,
+        row: parse_u64(row)?,
+        column: parse_u64(column)?,
+        grid: parse_optional_index(grid)?,
+    })
+}
+
+fn parse_popupmenu_select(popupmenu_select_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [selected] = extract_values(popupmenu_select_arguments, [Value::Nil])?;
+
+    Ok(RedrawEvent::PopupMenuSelect {
+        selected: parse_optional_index(selected)?,
+    })
+}
+
+fn parse_tab_info(tab_info: Value) -> Result<TabInfo> {
+    let mut tab = Value::Nil;
+    let mut name = String::new();
+
+    for (key, value) in parse_map(tab_info)?.into_iter() {
+        match parse_string(key)?.as_str() {
+            "tab" => tab = value,
+            "name" => name = parse_string(value)?,
+            _ => {}
+        }
+    }
+
+    Ok(TabInfo { tab, name })
+}
+
+fn parse_tabline_update(tabline_update_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    // Newer versions also send the current buffer and the buffer list, which aren't needed for
+    // drawing tabs
+    let [current_tab, tabs] = extract_values(tabline_update_arguments, [Value::Nil, Value::Nil])?;
+
+    Ok(RedrawEvent::TablineUpdate {
+        current_tab,
+        tabs: parse_array(tabs)?
+            .into_iter()
+            .map(parse_tab_info)
+            .collect::<Result<_>>()?,
+    })
+}
+
+fn parse_redraw_event_parameters(event_name: &str, event: Value) -> Result<Option<RedrawEvent>> {
+    let event_parameters = parse_array(event)?;
+
+    Ok(match event_name {
+        "set_title" => Some(parse_set_title(event_parameters)?),
+        // Events we know about but have no use for
+        "set_icon" | "bell" | "visual_bell" | "suspend" | "update_menu" | "chdir" => None,
+        "mode_info_set" => Some(parse_mode_info_set(event_parameters)?),
+        "option_set" => Some(parse_option_set(event_parameters)?),
+        "mode_change" => Some(parse_mode_change(event_parameters)?),
+        "mouse_on" => Some(RedrawEvent::MouseOn),
+        "mouse_off" => Some(RedrawEvent::MouseOff),
+        "busy_start" => Some(RedrawEvent::BusyStart),
+        "busy_stop" => Some(RedrawEvent::BusyStop),
+        "flush" => Some(RedrawEvent::Flush),
+        "grid_resize" => Some(parse_grid_resize(event_parameters)?),
+        "default_colors_set" => Some(parse_default_colors(event_parameters)?),
+        "hl_attr_define" => Some(parse_hl_attr_define(event_parameters)?),
+        "grid_line" => Some(parse_grid_line(event_parameters)?),
+        "grid_clear" => Some(parse_grid_clear(event_parameters)?),
+        "grid_destroy" => Some(parse_grid_destroy(event_parameters)?),
+        "grid_cursor_goto" => Some(parse_grid_cursor_goto(event_parameters)?),
+        "grid_scroll" => Some(parse_grid_scroll(event_parameters)?),
+        "win_pos" => Some(parse_win_pos(event_parameters)?),
+        "win_float_pos" => Some(parse_win_float_pos(event_parameters)?),
+        "win_external_pos" => Some(parse_win_external_pos(event_parameters)?),
+        "win_hide" => Some(parse_win_hide(event_parameters)?),
+        "win_close" => Some(parse_win_close(event_parameters)?),
+        "msg_set_pos" => Some(parse_msg_set_pos(event_parameters)?),
+        "win_viewport" => Some(parse_win_viewport(event_parameters)?),
+        "cmdline_show" => Some(parse_cmdline_show(event_parameters)?),
+        "cmdline_pos" => Some(parse_cmdline_pos(event_parameters)?),
+        "cmdline_special_char" => Some(parse_cmdline_special_char(event_parameters)?),
+        "cmdline_hide" => Some(RedrawEvent::CommandLineHide),
+        "cmdline_block_show" => Some(parse_cmdline_block_show(event_parameters)?),
+        "cmdline_block_append" => Some(parse_cmdline_block_append(event_parameters)?),
+        "cmdline_block_hide" => Some(RedrawEvent::CommandLineBlockHide),
+        "msg_show" => Some(parse_msg_show(event_parameters)?),
+        "msg_clear" => Some(RedrawEvent::MessageClear),
+        "msg_showmode" => Some(parse_msg_showmode(event_parameters)?),
+        "msg_showcmd" => Some(parse_msg_showcmd(event_parameters)?),
+        "msg_ruler" => Some(parse_msg_ruler(event_parameters)?),
+        "msg_history_show" => Some(parse_msg_history_show(event_parameters)?),
+        "hl_group_set" => Some(parse_hl_group_set(event_parameters)?),
+        "popupmenu_show" => Some(parse_popupmenu_show(event_parameters)?),
+        "popupmenu_select" => Some(parse_popupmenu_select(event_parameters)?),
+        "popupmenu_hide" => Some(RedrawEvent::PopupMenuHide),
+        "tabline_update" => Some(parse_tabline_update(event_parameters)?),
+        _ => return Err(ParseError::UnknownEvent),
+    })
+}
+
+// Parses a batch of redraw events sharing the same name. Events which can't be parsed are skipped
+// and returned alongside the others so that a single unexpected value doesn't take down the gui.
+pub fn parse_redraw_event(event_value: Value) -> (Vec<RedrawEvent>, Vec<RedrawEventError>) {
+    let mut event_contents = match parse_array(event_value) {
+        Ok(event_contents) => event_contents.into_iter(),
+        Err(error) => {
+            let event_name = "<unknown>".to_owned();
+            return (Vec::new(), vec![RedrawEventError { event_name, error }]);
+        }
+    };
+
+    let event_name = match event_contents.next().map(parse_string) {
+        Some(Ok(event_name)) => event_name,
+        Some(Err(error)) => {
+            let event_name = "<unknown>".to_owned();
+            return (Vec::new(), vec![RedrawEventError { event_name, error }]);
+        }
+        None => return (Vec::new(), Vec::new()),
+    };
 
     let events = event_contents;
     let mut parsed_events = Vec::with_capacity(events.len());
+    let mut errors = Vec::new();
 
     for event in events {
-        let event_parameters = parse_array(event)?;
-        let possible_parsed_event = match event_name.as_str() {
-            "set_title" => Some(parse_set_title(event_parameters)?),
-            "set_icon" => None, // Ignore set icon for now
-            "mode_info_set" => Some(parse_mode_info_set(event_parameters)?),
-            "option_set" => Some(parse_option_set(event_parameters)?),
-            "mode_change" => Some(parse_mode_change(event_parameters)?),
-            "mouse_on" => Some(RedrawEvent::MouseOn),
-            "mouse_off" => Some(RedrawEvent::MouseOff),
-            "busy_start" => Some(RedrawEvent::BusyStart),
-            "busy_stop" => Some(RedrawEvent::BusyStop),
-            "flush" => Some(RedrawEvent::Flush),
-            "grid_resize" => Some(parse_grid_resize(event_parameters)?),
-            "default_colors_set" => Some(parse_default_colors(event_parameters)?),
-            "hl_attr_define" => Some(parse_hl_attr_define(event_parameters)?),
-            "grid_line" => Some(parse_grid_line(event_parameters)?),
-            "grid_clear" => Some(parse_grid_clear(event_parameters)?),
-            "grid_destroy" => Some(parse_grid_destroy(event_parameters)?),
-            "grid_cursor_goto" => Some(parse_grid_cursor_goto(event_parameters)?),
-            "grid_scroll" => Some(parse_grid_scroll(event_parameters)?),
-            "win_pos" => Some(parse_win_pos(event_parameters)?),
-            "win_float_pos" => Some(parse_win_float_pos(event_parameters)?),
-            "win_external_pos" => Some(parse_win_external_pos(event_parameters)?),
-            "win_hide" => Some(parse_win_hide(event_parameters)?),
-            "win_close" => Some(parse_win_close(event_parameters)?),
-            "msg_set_pos" => Some(parse_msg_set_pos(event_parameters)?),
-            "win_viewport" => Some(parse_win_viewport(event_parameters)?),
-            "cmdline_show" => Some(parse_cmdline_show(event_parameters)?),
-            "cmdline_pos" => Some(parse_cmdline_pos(event_parameters)?),
-            "cmdline_special_char" => Some(parse_cmdline_special_char(event_parameters)?),
-            "cmdline_hide" => Some(RedrawEvent::CommandLineHide),
-            "cmdline_block_show" => Some(parse_cmdline_block_show(event_parameters)?),
-            "cmdline_block_append" => Some(parse_cmdline_block_append(event_parameters)?),
-            "cmdline_block_hide" => Some(RedrawEvent::CommandLineBlockHide),
-            "msg_show" => Some(parse_msg_show(event_parameters)?),
-            "msg_clear" => Some(RedrawEvent::MessageClear),
-            "msg_showmode" => Some(parse_msg_showmode(event_parameters)?),
-            "msg_showcmd" => Some(parse_msg_showcmd(event_parameters)?),
-            "msg_ruler" => Some(parse_msg_ruler(event_parameters)?),
-            "msg_history_show" => Some(parse_msg_history_show(event_parameters)?),
-            _ => None,
-        };
-
-        if let Some(parsed_event) = possible_parsed_event {
-            parsed_events.push(parsed_event);
+        match parse_redraw_event_parameters(&event_name, event) {
+            Ok(Some(parsed_event)) => parsed_events.push(parsed_event),
+            Ok(None) => {}
+            Err(ParseError::UnknownEvent) => {
+                // Every event in the batch shares the name, so reporting it once is enough
+                errors.push(RedrawEventError {
+                    event_name,
+                    error: ParseError::UnknownEvent,
+                });
+                break;
+            }
+            Err(error) => errors.push(RedrawEventError {
+                event_name: event_name.clone(),
+                error,
+            }),
         }
     }
 
-    Ok(parsed_events)
+    (parsed_events, errors)
 }
 
 pub fn parse_channel_stream_type(channel_stream_value: Value) -> Result<ChannelStreamType> {
@@ -1091,3 +1276,165 @@ pub fn parse_channel_list(channel_infos: Vec<Value>) -> Result<Vec<ChannelInfo>>
         .map(parse_channel_info)
         .collect::<Result<Vec<ChannelInfo>>>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_batch(name: &str, events: Vec<Vec<Value>>) -> Value {
+        let mut batch = vec![Value::from(name)];
+        batch.extend(events.into_iter().map(Value::Array));
+        Value::Array(batch)
+    }
+
+    #[test]
+    fn unknown_event_is_reported_once() {
+        let batch = event_batch("future_event", vec![vec![], vec![]]);
+        let (parsed_events, errors) = parse_redraw_event(batch);
+
+        assert!(parsed_events.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].event_name, "future_event");
+        assert!(matches!(errors[0].error, ParseError::UnknownEvent));
+    }
+
+    #[test]
+    fn malformed_event_does_not_drop_the_batch() {
+        let batch = event_batch(
+            "grid_resize",
+            vec![
+                vec![Value::from(1), Value::from(80), Value::from(24)],
+                vec![Value::from("not a grid")],
+                vec![Value::from(2), Value::from(40), Value::from(10)],
+            ],
+        );
+        let (parsed_events, errors) = parse_redraw_event(batch);
+
+        assert_eq!(parsed_events.len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].event_name, "grid_resize");
+    }
+
+    #[test]
+    fn extra_trailing_parameters_are_ignored() {
+        let batch = event_batch(
+            "grid_resize",
+            vec![vec![
+                Value::from(1),
+                Value::from(80),
+                Value::from(24),
+                Value::from("added in a later version"),
+            ]],
+        );
+        let (parsed_events, errors) = parse_redraw_event(batch);
+
+        assert!(errors.is_empty());
+        assert!(matches!(
+            parsed_events[0],
+            RedrawEvent::Resize {
+                grid: 1,
+                width: 80,
+                height: 24
+            }
+        ));
+    }
+    #[test]
+    fn popupmenu_show_without_selection_or_grid() {
+        let item = |word: &str| {
+            Value::from(vec![
+                Value::from(word),
+                Value::from("f"),
+                Value::from(""),
+                Value::from(""),
+            ])
+        };
+        let batch = event_batch(
+            "popupmenu_show",
+            vec![vec![
+                Value::from(vec![item("foo"), item("bar")]),
+                Value::from(-1),
+                Value::from(0),
+                Value::from(5),
+                Value::from(-1),
+            ]],
+        );
+        let (parsed_events, errors) = parse_redraw_event(batch);
+
+        assert!(errors.is_empty());
+        match &parsed_events[0] {
+            RedrawEvent::PopupMenuShow {
+                items,
+                selected,
+                column,
+                grid,
+                ..
+            } => {
+                assert_eq!(items.len(), 2);
+                assert_eq!(items[1].word, "bar");
+                assert_eq!(items[1].kind, "f");
+                assert_eq!(*selected, None);
+                assert_eq!(*column, 5);
+                assert_eq!(*grid, None);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn tabline_update_keeps_tab_handles() {
+        let tab = |handle: u8, name: &str| {
+            Value::Map(vec![
+                (Value::from("tab"), Value::Ext(2, vec![handle])),
+                (Value::from("name"), Value::from(name)),
+            ])
+        };
+        let batch = event_batch(
+            "tabline_update",
+            vec![vec![
+                Value::Ext(2, vec![2]),
+                Value::from(vec![tab(1, "main.rs"), tab(2, "lib.rs")]),
+                Value::Ext(0, vec![1]),
+                Value::from(Vec::<Value>::new()),
+            ]],
+        );
+        let (parsed_events, errors) = parse_redraw_event(batch);
+
+        assert!(errors.is_empty());
+        match &parsed_events[0] {
+            RedrawEvent::TablineUpdate { current_tab, tabs } => {
+                assert_eq!(*current_tab, Value::Ext(2, vec![2]));
+                assert_eq!(tabs.len(), 2);
+                assert_eq!(tabs[1].tab, *current_tab);
+                assert_eq!(tabs[1].name, "lib.rs");
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn window_positions_keep_window_handles() {
+        // Handle 1000, encoded as a msgpack uint16
+        let window = Value::Ext(1, vec![0xcd, 0x03, 0xe8]);
+        let batch = event_batch(
+            "win_pos",
+            vec![vec![
+                Value::from(2),
+                window,
+                Value::from(0),
+                Value::from(0),
+                Value::from(80),
+                Value::from(24),
+            ]],
+        );
+        let (parsed_events, errors) = parse_redraw_event(batch);
+
+        assert!(errors.is_empty());
+        match &parsed_events[0] {
+            RedrawEvent::WindowPosition { grid, window, .. } => {
+                assert_eq!(*grid, 2);
+                assert_eq!(*window, 1000);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+}
@@ -1,33 +1,135 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use log::trace;
+use log::{error, trace, warn};
 use nvim_rs::{Handler, Neovim};
 use parking_lot::Mutex;
 use rmpv::Value;
 use tokio::task;
 
-use super::events::{parse_redraw_event, RedrawEvent};
+use super::api::{self, NeovideNotification};
+use super::clipboard::{Clipboard, SystemClipboard};
+use super::events::{parse_redraw_event, ParseError, RedrawEventError};
+use super::recording::RedrawRecorder;
 use super::ui_commands::UiCommand;
-use crate::bridge::TxWrapper;
+use crate::bridge::{TxWrapper, EXIT_CODE};
 use crate::channel_utils::*;
-use crate::error_handling::ResultPanicExplanation;
+use crate::cmd_line::CmdLineSettings;
+use crate::editor::{EditorCommand, WindowCommand};
 use crate::settings::SETTINGS;
+use crate::window::WindowSettings;
+
+fn create_redraw_recorder() -> Option<Arc<Mutex<RedrawRecorder>>> {
+    let path = SETTINGS.get::<CmdLineSettings>().record?;
+
+    match RedrawRecorder::create(&path) {
+        Ok(recorder) => Some(Arc::new(Mutex::new(recorder))),
+        Err(error) => {
+            error!("Could not create recording {}: {}", path, error);
+            None
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct NeovimHandler {
     ui_command_sender: Arc<Mutex<LoggingTx<UiCommand>>>,
-    redraw_event_sender: Arc<Mutex<LoggingTx<RedrawEvent>>>,
+    editor_command_sender: Arc<Mutex<LoggingTx<EditorCommand>>>,
+    window_command_sender: Arc<Mutex<LoggingSender<WindowCommand>>>,
+    redraw_recorder: Option<Arc<Mutex<RedrawRecorder>>>,
+    reported_parse_errors: Arc<Mutex<HashSet<String>>>,
+    clipboard: Arc<Mutex<Clipboard>>,
+    // Set once neovim announces that it is exiting, after which a closed connection is final
+    neovim_quit: Arc<AtomicBool>,
 }
 
 impl NeovimHandler {
     pub fn new(
         ui_command_sender: LoggingTx<UiCommand>,
-        redraw_event_sender: LoggingTx<RedrawEvent>,
+        editor_command_sender: LoggingTx<EditorCommand>,
+        window_command_sender: LoggingSender<WindowCommand>,
     ) -> NeovimHandler {
         NeovimHandler {
             ui_command_sender: Arc::new(Mutex::new(ui_command_sender)),
-            redraw_event_sender: Arc::new(Mutex::new(redraw_event_sender)),
+            editor_command_sender: Arc::new(Mutex::new(editor_command_sender)),
+            window_command_sender: Arc::new(Mutex::new(window_command_sender)),
+            redraw_recorder: create_redraw_recorder(),
+            reported_parse_errors: Arc::new(Mutex::new(HashSet::new())),
+            clipboard: Arc::new(Mutex::new(Clipboard::new(Box::new(
+                SystemClipboard::default(),
+            )))),
+            neovim_quit: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn neovim_quit(&self) -> bool {
+        self.neovim_quit.load(Ordering::Relaxed)
+    }
+
+    async fn report_parse_error(&self, parse_error: RedrawEventError, neovim: &Neovim<TxWrapper>) {
+        // Only the first failure for each event is shown, otherwise an event sent on every redraw
+        // would flood the message area.
+        let first_occurrence = self
+            .reported_parse_errors
+            .lock()
+            .insert(parse_error.event_name.clone());
+
+        if !first_occurrence {
+            trace!("Skipped redraw event {}", parse_error);
+        } else if let ParseError::UnknownEvent = parse_error.error {
+            warn!("Ignoring unknown redraw event {}", parse_error.event_name);
+        } else {
+            let message = format!("Neovide could not parse redraw event {}", parse_error);
+            error!("{}", message);
+            neovim.err_writeln(&message).await.ok();
+        }
+    }
+
+    async fn handle_neovide_notification(
+        &self,
+        notification: NeovideNotification,
+        neovim: &Neovim<TxWrapper>,
+    ) {
+        match notification {
+            NeovideNotification::ToggleFullscreen => {
+                // The window follows g:neovide_fullscreen, so flipping the variable keeps the two
+                // in sync
+                let fullscreen = SETTINGS.get::<WindowSettings>().fullscreen;
+                neovim
+                    .set_var("neovide_fullscreen", Value::from(!fullscreen))
+                    .await
+                    .ok();
+            }
+            NeovideNotification::SetFontSize(size) => api::set_font_size(neovim, size).await,
+            NeovideNotification::Screenshot(request) => {
+                let window_command_sender = self.window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::Screenshot(request))
+                    .ok();
+            }
+            NeovideNotification::RecordStart(request) => {
+                let window_command_sender = self.window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::StartScreencast(request))
+                    .ok();
+            }
+            NeovideNotification::RecordStop => {
+                let window_command_sender = self.window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::StopScreencast)
+                    .ok();
+            }
+            NeovideNotification::FocusWindow => {
+                let window_command_sender = self.window_command_sender.lock();
+                window_command_sender.send(WindowCommand::FocusWindow).ok();
+            }
+            NeovideNotification::Quit(exit_code) => {
+                // A child's own exit status is recorded again once it has exited
+                EXIT_CODE.store(exit_code, Ordering::Relaxed);
+                self.neovim_quit.store(true, Ordering::Relaxed);
+            }
         }
     }
 }
@@ -40,42 +142,84 @@ impl Handler for NeovimHandler {
         &self,
         event_name: String,
         arguments: Vec<Value>,
-        _neovim: Neovim<TxWrapper>,
+        neovim: Neovim<TxWrapper>,
     ) {
         trace!("Neovim notification: {:?}", &event_name);
 
+        if let Some(notification) = NeovideNotification::parse(&event_name, &arguments) {
+            self.handle_neovide_notification(notification, &neovim)
+                .await;
+            return;
+        }
+
         #[cfg(windows)]
         let ui_command_sender = self.ui_command_sender.clone();
 
-        let redraw_event_sender = self.redraw_event_sender.clone();
-        task::spawn_blocking(move || match event_name.as_ref() {
+        let editor_command_sender = self.editor_command_sender.clone();
+        let redraw_recorder = self.redraw_recorder.clone();
+        let parse_errors = task::spawn_blocking(move || match event_name.as_ref() {
             "redraw" => {
+                if let Some(redraw_recorder) = redraw_recorder {
+                    if let Err(error) = redraw_recorder.lock().record(&arguments) {
+                        error!("Could not record redraw notification: {}", error);
+                    }
+                }
+
+                let mut parse_errors = Vec::new();
                 for events in arguments {
-                    let parsed_events = parse_redraw_event(events)
-                        .unwrap_or_explained_panic("Could not parse event from neovim");
+                    let (parsed_events, errors) = parse_redraw_event(events);
+                    parse_errors.extend(errors);
 
                     for parsed_event in parsed_events {
-                        let redraw_event_sender = redraw_event_sender.lock();
-                        redraw_event_sender.send(parsed_event).ok();
+                        let editor_command_sender = editor_command_sender.lock();
+                        editor_command_sender
+                            .send(EditorCommand::NeovimRedrawEvent(parsed_event))
+                            .ok();
                     }
                 }
+                parse_errors
             }
             "setting_changed" => {
                 SETTINGS.handle_changed_notification(arguments);
+                Vec::new()
             }
             #[cfg(windows)]
             "neovide.register_right_click" => {
                 let ui_command_sender = ui_command_sender.lock();
                 ui_command_sender.send(UiCommand::RegisterRightClick).ok();
+                Vec::new()
             }
             #[cfg(windows)]
             "neovide.unregister_right_click" => {
                 let ui_command_sender = ui_command_sender.lock();
                 ui_command_sender.send(UiCommand::UnregisterRightClick).ok();
+                Vec::new()
             }
-            _ => {}
+            _ => Vec::new(),
         })
         .await
-        .ok();
+        .unwrap_or_default();
+
+        for parse_error in parse_errors {
+            self.report_parse_error(parse_error, &neovim).await;
+        }
+    }
+    async fn handle_request(
+        &self,
+        event_name: String,
+        arguments: Vec<Value>,
+        _neovim: Neovim<TxWrapper>,
+    ) -> Result<Value, Value> {
+        trace!("Neovim request: {:?}", &event_name);
+
+        // Reading the clipboard can block while another application answers
+        let clipboard = self.clipboard.clone();
+        let request_name = event_name.clone();
+        let clipboard_response =
+            task::spawn_blocking(move || clipboard.lock().handle_request(&request_name, arguments))
+                .await
+                .unwrap_or_else(|_| Some(Err(Value::from("Clipboard request failed"))));
+
+        clipboard_response.unwrap_or_else(|| api::handle_request(&event_name))
     }
 }
@@ -1,32 +1,58 @@
+mod api;
+mod capabilities;
+mod clipboard;
+mod command;
 pub mod create;
 mod events;
 mod handler;
+mod recording;
+mod single_instance;
+mod startup;
 mod tx_wrapper;
 mod ui_commands;
 
 use std::path::Path;
-use std::process::Stdio;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use crossfire::mpsc::RxUnbounded;
+use crossfire::mpsc::{unbounded_future, RxUnbounded};
 use log::{error, info, warn};
-use nvim_rs::UiAttachOptions;
+use nvim_rs::error::{CallError, LoopError};
+use nvim_rs::Neovim;
+use parking_lot::Mutex;
 use rmpv::Value;
-use tokio::process::Command;
+use tokio::process::Child;
 use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+use tokio::time::{delay_for, timeout};
 
 use crate::channel_utils::*;
+use crate::cmd_line::CmdLineSettings;
+use crate::editor::{EditorCommand, WindowCommand};
 use crate::settings::*;
-use crate::{cmd_line::CmdLineSettings, error_handling::ResultPanicExplanation};
+pub use api::{ScreencastRequest, ScreenshotRequest, ScreenshotWindow};
+pub use capabilities::{NeovimCapabilities, NeovimVersion, UiExtensions};
 pub use events::*;
 use handler::NeovimHandler;
-use regex::Regex;
+pub use recording::start_replay;
+use single_instance::start_single_instance_listener;
+pub use single_instance::{forward_to_running_instance, SingleInstanceSettings};
+use startup::StderrLog;
+pub use startup::{StartupFailure, StartupFailureReason};
 pub use tx_wrapper::{TxWrapper, WrapTx};
-pub use ui_commands::UiCommand;
+use ui_commands::coalesce_ui_commands;
+pub use ui_commands::{OpenMode, UiCommand};
+
+// The code neovide exits with once it stops running. It follows neovim's, so that `:cq` makes
+// whatever launched the editor, such as git, abort.
+pub static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
 
 #[cfg(windows)]
 fn set_windows_creation_flags(cmd: &mut Command) {
+    use std::os::windows::process::CommandExt;
+
     cmd.creation_flags(0x0800_0000); // CREATE_NO_WINDOW
 }
 
@@ -56,21 +82,21 @@ fn platform_build_nvim_cmd(bin: &str) -> Option<Command> {
     }
 }
 
-fn build_nvim_cmd() -> Command {
+fn build_nvim_cmd() -> Result<Command, StartupFailureReason> {
     if let Some(path) = SETTINGS.get::<CmdLineSettings>().neovim_bin {
         if let Some(cmd) = platform_build_nvim_cmd(&path) {
-            return cmd;
+            return Ok(cmd);
         } else {
             warn!("NEOVIM_BIN is invalid falling back to first bin in PATH");
         }
     }
     #[cfg(windows)]
     if SETTINGS.get::<CmdLineSettings>().wsl {
-        if let Ok(output) = std::process::Command::new("wsl")
+        return match Command::new("wsl")
             .args(&["bash", "-ic", "which nvim"])
             .output()
         {
-            if output.status.success() {
+            Ok(output) if output.status.success() => {
                 let path = String::from_utf8(output.stdout).unwrap();
                 let mut cmd = Command::new("wsl");
                 cmd.args(&[
@@ -78,26 +104,19 @@ fn build_nvim_cmd() -> Command {
                     "-c",
                     "let \\$PATH=system(\"bash -ic 'echo \\$PATH' 2>/dev/null\")",
                 ]);
-                return cmd;
-            } else {
-                error!("nvim not found in WSL path");
-                std::process::exit(1);
+                Ok(cmd)
             }
-        } else {
-            error!("wsl which nvim failed");
-            std::process::exit(1);
-        }
+            Ok(_) => Err(StartupFailureReason::NotFound),
+            Err(error) => Err(StartupFailureReason::SpawnFailed(format!(
+                "wsl which nvim failed: {}",
+                error
+            ))),
+        };
     }
     if let Ok(path) = which::which("nvim") {
-        if let Some(cmd) = platform_build_nvim_cmd(path.to_str().unwrap()) {
-            cmd
-        } else {
-            error!("nvim does not have proper permissions!");
-            std::process::exit(1);
-        }
+        platform_build_nvim_cmd(path.to_str().unwrap()).ok_or(StartupFailureReason::BadPermissions)
     } else {
-        error!("nvim not found!");
-        std::process::exit(1);
+        Err(StartupFailureReason::NotFound)
     }
 }
 
@@ -121,87 +140,227 @@ pub fn build_neovide_command(channel: u64, num_args: u64, command: &str, event:
     };
 }
 
-pub fn create_nvim_command() -> Command {
-    let mut cmd = build_nvim_cmd();
+pub fn create_nvim_command() -> Result<Command, StartupFailureReason> {
+    let settings = SETTINGS.get::<CmdLineSettings>();
+    let mut neovim_arguments = vec!["--embed".to_owned()];
+    neovim_arguments.extend(settings.neovim_args.iter().cloned());
+    neovim_arguments.extend(settings.files_to_open.iter().cloned());
 
-    cmd.arg("--embed")
-        .args(SETTINGS.get::<CmdLineSettings>().neovim_args.iter())
-        .args(SETTINGS.get::<CmdLineSettings>().files_to_open.iter());
+    // With a neovim command the binary lives wherever that command runs, so NEOVIM_BIN is passed
+    // along as is instead of being looked up here
+    let wrapped_cmd = settings.neovim_command.as_ref().and_then(|template| {
+        command::build_wrapped_command(template, settings.neovim_bin.as_deref(), &neovim_arguments)
+    });
+    let mut cmd = match wrapped_cmd {
+        Some(cmd) => cmd,
+        None => {
+            let mut cmd = build_nvim_cmd()?;
+            cmd.args(&neovim_arguments);
+            cmd
+        }
+    };
 
     info!("Starting neovim with: {:?}", cmd);
 
-    #[cfg(not(debug_assertions))]
+    // Stderr is read by the StderrLog, which shows it on the error screen if neovim fails to start
     cmd.stderr(Stdio::piped());
 
-    #[cfg(debug_assertions)]
-    cmd.stderr(Stdio::inherit());
-
     #[cfg(windows)]
     set_windows_creation_flags(&mut cmd);
 
-    cmd
+    Ok(cmd)
 }
 
 enum ConnectionMode {
     Child,
     RemoteTcp(String),
+    LocalServer(String),
+}
+
+impl ConnectionMode {
+    // A child process that exits is gone for good, but remote servers may come back after the
+    // connection drops. Servers which quit on purpose say so before closing the connection.
+    fn can_reconnect(&self) -> bool {
+        !matches!(self, ConnectionMode::Child)
+    }
 }
 
 fn connection_mode() -> ConnectionMode {
     if let Some(arg) = SETTINGS.get::<CmdLineSettings>().remote_tcp {
         ConnectionMode::RemoteTcp(arg)
+    } else if let Some(arg) = SETTINGS.get::<CmdLineSettings>().server {
+        ConnectionMode::LocalServer(arg)
     } else {
         ConnectionMode::Child
     }
 }
 
-async fn start_neovim_runtime(
-    ui_command_sender: LoggingTx<UiCommand>,
-    ui_command_receiver: RxUnbounded<UiCommand>,
-    redraw_event_sender: LoggingTx<RedrawEvent>,
-    running: Arc<AtomicBool>,
-) {
-    let handler = NeovimHandler::new(ui_command_sender.clone(), redraw_event_sender.clone());
-    let (mut nvim, io_handler) = match connection_mode() {
-        ConnectionMode::Child => create::new_child_cmd(&mut create_nvim_command(), handler).await,
-        ConnectionMode::RemoteTcp(address) => create::new_tcp(address, handler).await,
+type IoHandle = JoinHandle<Result<(), Box<LoopError>>>;
+
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(250);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+// How long to wait for the rest of neovim's stderr after a failed start
+const STDERR_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+// How long to wait for neovim to exit once it has closed the connection
+const CHILD_EXIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct Connection {
+    nvim: Neovim<TxWrapper>,
+    io_handler: IoHandle,
+    // Only set when neovide started neovim itself
+    child: Option<Child>,
+    // What was started or connected to, for the error screen
+    command: String,
+    stderr_log: StderrLog,
+    stderr_reader: Option<JoinHandle<()>>,
+}
+
+impl Connection {
+    fn new(nvim: Neovim<TxWrapper>, io_handler: IoHandle, command: String) -> Connection {
+        Connection {
+            nvim,
+            io_handler,
+            child: None,
+            command,
+            stderr_log: StderrLog::default(),
+            stderr_reader: None,
+        }
     }
-    .unwrap_or_explained_panic("Could not locate or start neovim process");
 
-    if nvim.get_api_info().await.is_err() {
-        error!("Cannot get neovim api info, either neovide is launched with an unknown command line option or neovim version not supported!");
-        std::process::exit(-1);
+    // Stops whatever is left of a neovim that failed to start and collects what it printed
+    async fn into_failure(mut self, reason: StartupFailureReason) -> StartupFailure {
+        if let Some(child) = self.child.as_mut() {
+            child.kill().ok();
+        }
+        if let Some(stderr_reader) = self.stderr_reader.take() {
+            timeout(STDERR_DRAIN_TIMEOUT, stderr_reader).await.ok();
+        }
+
+        StartupFailure {
+            reason,
+            command: self.command,
+            stderr: self.stderr_log.lines(),
+        }
     }
+}
 
-    let close_watcher_running = running.clone();
-    tokio::spawn(async move {
-        info!("Close watcher started");
-        match io_handler.await {
-            Err(join_error) => error!("Error joining IO loop: '{}'", join_error),
-            Ok(Err(error)) => {
-                if !error.is_channel_closed() {
-                    error!("Error: '{}'", error);
-                }
+async fn connect(
+    connection_mode: &ConnectionMode,
+    handler: NeovimHandler,
+) -> Result<Connection, StartupFailure> {
+    let failure = |reason, command: &str| StartupFailure {
+        reason,
+        command: command.to_owned(),
+        stderr: Vec::new(),
+    };
+
+    match connection_mode {
+        ConnectionMode::Child => {
+            let cmd = create_nvim_command().map_err(|reason| failure(reason, "nvim"))?;
+            let command = format!("{:?}", cmd);
+            let (nvim, io_handler, mut child) =
+                create::new_child_cmd(&mut tokio::process::Command::from(cmd), handler)
+                    .await
+                    .map_err(|error| {
+                        failure(StartupFailureReason::from_spawn_error(&error), &command)
+                    })?;
+
+            let mut connection = Connection::new(nvim, io_handler, command);
+            connection.stderr_reader = child
+                .stderr
+                .take()
+                .map(|stderr| connection.stderr_log.capture(stderr));
+            connection.child = Some(child);
+            Ok(connection)
+        }
+        ConnectionMode::RemoteTcp(address) => {
+            let command = format!("--remote-tcp={}", address);
+            let (nvim, io_handler) =
+                create::new_tcp(address.as_str(), handler)
+                    .await
+                    .map_err(|error| {
+                        failure(
+                            StartupFailureReason::ConnectionFailed(error.to_string()),
+                            &command,
+                        )
+                    })?;
+            Ok(Connection::new(nvim, io_handler, command))
+        }
+        ConnectionMode::LocalServer(path) => {
+            let command = format!("--server={}", path);
+            let (nvim, io_handler) =
+                create::new_unix_socket(path, handler)
+                    .await
+                    .map_err(|error| {
+                        failure(
+                            StartupFailureReason::ConnectionFailed(error.to_string()),
+                            &command,
+                        )
+                    })?;
+            Ok(Connection::new(nvim, io_handler, command))
+        }
+    }
+}
+
+// Takes the exit code of a neovim started by neovide as neovide's own
+async fn record_exit_code(child: Child) {
+    match timeout(CHILD_EXIT_TIMEOUT, child).await {
+        Ok(Ok(status)) => {
+            info!("Neovim exited with {}", status);
+            // Killed by a signal without an exit code
+            EXIT_CODE.store(status.code().unwrap_or(1), Ordering::Relaxed);
+        }
+        Ok(Err(error)) => error!("Could not get neovim's exit status: {}", error),
+        Err(_) => warn!("Neovim did not exit after closing the connection"),
+    }
+}
+
+async fn wait_for_disconnect(io_handler: IoHandle) {
+    match io_handler.await {
+        Err(join_error) => error!("Error joining IO loop: '{}'", join_error),
+        Ok(Err(error)) => {
+            if !error.is_channel_closed() {
+                error!("Error: '{}'", error);
             }
-            Ok(Ok(())) => {}
-        };
-        close_watcher_running.store(false, Ordering::Relaxed);
-    });
+        }
+        Ok(Ok(())) => {}
+    };
+}
 
-    if let Ok(output) = nvim.command_output("version").await {
-        let re = Regex::new(r"NVIM v0.[4-9]\d*.\d+").unwrap();
-        if !re.is_match(&output) {
-            error!("Neovide requires nvim version 0.4 or higher. Download the latest version here https://github.com/neovim/neovim/wiki/Installing-Neovim");
-            std::process::exit(0);
+async fn setup_neovim_session(
+    nvim: &Neovim<TxWrapper>,
+) -> Result<NeovimCapabilities, StartupFailureReason> {
+    // Fails when neovim exits right away, such as for an unknown command line option
+    let api_info = nvim
+        .get_api_info()
+        .await
+        .map_err(|error| StartupFailureReason::NoResponse(error.to_string()))?;
+
+    let capabilities = match capabilities::parse_api_info(api_info) {
+        Ok(capabilities) if !capabilities.is_supported() => {
+            return Err(StartupFailureReason::UnsupportedVersion(
+                capabilities.version.to_string(),
+            ));
+        }
+        Ok(capabilities) => {
+            info!(
+                "Connected to neovim {} with api level {}",
+                capabilities.version, capabilities.api_level
+            );
+            capabilities
+        }
+        // Api info that can't be read doesn't mean the server is too old, so it is let through
+        // without any of the ui extensions it couldn't be asked about
+        Err(error) => {
+            warn!("Could not parse neovim api info: {}", error);
+            NeovimCapabilities::default()
         }
-    } else {
-        error!("Neovide requires nvim version 0.4 or higher. Download the latest version here https://github.com/neovim/neovim/wiki/Installing-Neovim");
-        std::process::exit(0);
     };
 
     nvim.set_var("neovide", Value::Boolean(true))
         .await
-        .unwrap_or_explained_panic("Could not communicate with neovim process");
+        .map_err(|error| StartupFailureReason::NoResponse(error.to_string()))?;
 
     if let Err(command_error) = nvim.command("runtime! ginit.vim").await {
         nvim.command(&format!(
@@ -247,6 +406,9 @@ async fn start_neovim_runtime(
         neovide_channel
     );
 
+    api::setup_neovide_api(nvim, neovide_channel, capabilities.api_level).await;
+    clipboard::register_clipboard_provider(nvim, neovide_channel).await;
+
     #[cfg(windows)]
     nvim.command(&build_neovide_command(
         neovide_channel,
@@ -274,47 +436,342 @@ async fn start_neovim_runtime(
         .await
         .ok();
 
-    let WindowGeometry { width, height } = SETTINGS.get::<CmdLineSettings>().geometry;
-    let mut options = UiAttachOptions::new();
-    options.set_linegrid_external(true);
+    Ok(capabilities)
+}
 
-    if SETTINGS.get::<CmdLineSettings>().multi_grid {
-        options.set_multigrid_external(true);
+// The extensions neovide knows how to draw itself. Anything not listed here is left to neovim to
+// render into the grid.
+fn requested_ui_extensions() -> UiExtensions {
+    UiExtensions {
+        multigrid: SETTINGS.get::<CmdLineSettings>().multi_grid,
+        cmdline: true,
+        messages: SETTINGS.get::<CmdLineSettings>().ext_messages,
+        popupmenu: true,
+        tabline: SETTINGS.get::<CmdLineSettings>().ext_tabline,
+    }
+}
+
+async fn attach_ui(
+    nvim: &Neovim<TxWrapper>,
+    capabilities: NeovimCapabilities,
+    editor_command_sender: &LoggingTx<EditorCommand>,
+    (width, height): (u64, u64),
+) -> Result<(), Box<CallError>> {
+    let requested_extensions = requested_ui_extensions();
+    let ui_extensions = capabilities.negotiate(requested_extensions);
+    if ui_extensions != requested_extensions {
+        warn!(
+            "Neovim {} does not support all requested ui extensions, attaching with {:?}",
+            capabilities.version, ui_extensions
+        );
     }
-    options.set_rgb(true);
-    nvim.ui_attach(width as i64, height as i64, &options)
-        .await
-        .unwrap_or_explained_panic("Could not attach ui to neovim process");
+
+    // The editor has to know what was negotiated before the first redraw arrives
+    editor_command_sender
+        .send(EditorCommand::CapabilitiesChanged {
+            capabilities,
+            ui_extensions,
+        })
+        .ok();
+
+    nvim.ui_attach(width as i64, height as i64, &ui_extensions.attach_options())
+        .await?;
 
     info!("Neovim process attached");
 
-    let nvim = Arc::new(nvim);
+    SETTINGS.read_initial_values(nvim).await;
+    SETTINGS.setup_changed_listeners(nvim).await;
+
+    Ok(())
+}
+
+// Retries the connection with an exponential backoff until it succeeds and the ui is attached
+// again. Returns None if neovide was closed while waiting.
+async fn reconnect(
+    connection_mode: &ConnectionMode,
+    handler: &NeovimHandler,
+    editor_command_sender: &LoggingTx<EditorCommand>,
+    grid_size: &Mutex<(u64, u64)>,
+    running: &AtomicBool,
+) -> Option<(Neovim<TxWrapper>, IoHandle)> {
+    let mut delay = RECONNECT_INITIAL_DELAY;
+
+    while running.load(Ordering::Relaxed) {
+        delay_for(delay).await;
+        delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+
+        // The quit notification may be handled just after the connection closed
+        if handler.neovim_quit() {
+            return None;
+        }
+
+        let Connection {
+            nvim, io_handler, ..
+        } = match connect(connection_mode, handler.clone()).await {
+            Ok(connection) => connection,
+            Err(failure) => {
+                info!(
+                    "Reconnect failed, retrying in {:?}: {}",
+                    delay, failure.reason
+                );
+                continue;
+            }
+        };
+
+        // The editor has to drop the grids of the previous session before the attach sends the
+        // new ones
+        editor_command_sender
+            .send(EditorCommand::ConnectionRestored)
+            .ok();
+
+        let grid_size = *grid_size.lock();
+        let attached = match setup_neovim_session(&nvim).await {
+            Ok(capabilities) => attach_ui(&nvim, capabilities, editor_command_sender, grid_size)
+                .await
+                .map_err(|error| error.to_string()),
+            Err(reason) => Err(reason.to_string()),
+        };
+        match attached {
+            Ok(()) => return Some((nvim, io_handler)),
+            Err(error) => {
+                warn!("Could not reattach ui to neovim: {}", error);
+                editor_command_sender
+                    .send(EditorCommand::ConnectionLost)
+                    .ok();
+            }
+        }
+    }
 
-    let ui_command_running = running.clone();
-    let input_nvim = nvim.clone();
+    None
+}
+
+// Starts neovim and attaches the ui for the first time
+async fn start_session(
+    connection_mode: &ConnectionMode,
+    handler: &NeovimHandler,
+    editor_command_sender: &LoggingTx<EditorCommand>,
+    grid_size: (u64, u64),
+) -> Result<Connection, StartupFailure> {
+    let connection = connect(connection_mode, handler.clone()).await?;
+
+    let capabilities = match setup_neovim_session(&connection.nvim).await {
+        Ok(capabilities) => capabilities,
+        Err(reason) => return Err(connection.into_failure(reason).await),
+    };
+
+    if let Err(error) = attach_ui(
+        &connection.nvim,
+        capabilities,
+        editor_command_sender,
+        grid_size,
+    )
+    .await
+    {
+        let reason = StartupFailureReason::AttachFailed(error.to_string());
+        return Err(connection.into_failure(reason).await);
+    }
+
+    Ok(connection)
+}
+
+// Waits on the error screen until the user asks to retry, returning false if they would rather
+// quit
+async fn wait_for_retry(
+    ui_command_receiver: &RxUnbounded<UiCommand>,
+    grid_size: &Mutex<(u64, u64)>,
+) -> bool {
+    while let Ok(ui_command) = ui_command_receiver.recv().await {
+        match ui_command {
+            UiCommand::Keyboard(input) => match input.as_str() {
+                "r" | "R" | "<CR>" => return true,
+                "q" | "Q" | "<Esc>" => return false,
+                _ => {}
+            },
+            UiCommand::Resize { width, height } => {
+                *grid_size.lock() = (width as u64, height as u64);
+            }
+            UiCommand::Quit => return false,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+async fn start_neovim_runtime(
+    ui_command_sender: LoggingTx<UiCommand>,
+    ui_command_receiver: RxUnbounded<UiCommand>,
+    editor_command_sender: LoggingTx<EditorCommand>,
+    window_command_sender: LoggingSender<WindowCommand>,
+    running: Arc<AtomicBool>,
+) {
+    let handler = NeovimHandler::new(
+        ui_command_sender.clone(),
+        editor_command_sender.clone(),
+        window_command_sender.clone(),
+    );
+    let connection_mode = connection_mode();
+
+    let WindowGeometry { width, height } = SETTINGS.get::<CmdLineSettings>().geometry;
+    let grid_size = Arc::new(Mutex::new((width, height)));
+
+    // Failures are shown in the window instead of closing it, so that the user gets to see why
+    let Connection {
+        nvim,
+        io_handler,
+        child,
+        ..
+    } = loop {
+        let attach_size = *grid_size.lock();
+        match start_session(
+            &connection_mode,
+            &handler,
+            &editor_command_sender,
+            attach_size,
+        )
+        .await
+        {
+            Ok(connection) => break connection,
+            Err(failure) => {
+                error!("Could not start neovim: {}", failure.reason);
+                editor_command_sender
+                    .send(EditorCommand::StartupFailed(failure))
+                    .ok();
+
+                if !wait_for_retry(&ui_command_receiver, &grid_size).await {
+                    EXIT_CODE.store(1, Ordering::Relaxed);
+                    running.store(false, Ordering::Relaxed);
+                    return;
+                }
+                editor_command_sender
+                    .send(EditorCommand::StartupRetried)
+                    .ok();
+            }
+        }
+    };
+
+    // Only once neovim is up, so that other invocations keep starting their own window while this
+    // one is still on the error screen
+    if SETTINGS.get::<CmdLineSettings>().single_instance {
+        start_single_instance_listener(ui_command_sender, window_command_sender);
+    }
+
+    // The connection is swapped out when a remote session reconnects, so every user of it reads
+    // the current one from here. None while disconnected.
+    let current_nvim = Arc::new(Mutex::new(Some(Arc::new(nvim))));
+
+    let close_watcher_running = running.clone();
+    let close_watcher_nvim = current_nvim.clone();
+    let close_watcher_grid_size = grid_size.clone();
+    let close_watcher_editor_command_sender = editor_command_sender.clone();
     tokio::spawn(async move {
+        info!("Close watcher started");
+        let mut io_handler = io_handler;
         loop {
-            if !ui_command_running.load(Ordering::Relaxed) {
+            wait_for_disconnect(io_handler).await;
+
+            if !connection_mode.can_reconnect()
+                || handler.neovim_quit()
+                || !close_watcher_running.load(Ordering::Relaxed)
+            {
                 break;
             }
 
-            match ui_command_receiver.recv().await {
-                Ok(ui_command) => {
-                    let input_nvim = input_nvim.clone();
-                    tokio::spawn(async move {
-                        ui_command.execute(&input_nvim).await;
-                    });
-                }
-                Err(_) => {
-                    ui_command_running.store(false, Ordering::Relaxed);
-                    break;
+            warn!("Connection to neovim lost, attempting to reconnect");
+            close_watcher_nvim.lock().take();
+            close_watcher_editor_command_sender
+                .send(EditorCommand::ConnectionLost)
+                .ok();
+
+            match reconnect(
+                &connection_mode,
+                &handler,
+                &close_watcher_editor_command_sender,
+                &close_watcher_grid_size,
+                &close_watcher_running,
+            )
+            .await
+            {
+                Some((nvim, new_io_handler)) => {
+                    info!("Reconnected to neovim");
+                    *close_watcher_nvim.lock() = Some(Arc::new(nvim));
+                    io_handler = new_io_handler;
                 }
+                None => break,
             }
         }
+        if let Some(child) = child {
+            record_exit_code(child).await;
+        }
+        close_watcher_running.store(false, Ordering::Relaxed);
     });
 
-    SETTINGS.read_initial_values(&nvim).await;
-    SETTINGS.setup_changed_listeners(&nvim).await;
+    tokio::spawn(execute_ui_commands(
+        ui_command_receiver,
+        current_nvim,
+        grid_size,
+        running,
+    ));
+}
+
+// Runs the input commands one at a time so that input reaches neovim in the order it was typed.
+// Whatever queued up while the previous command was in flight is coalesced into one batch.
+// Commands which may stop at a prompt go to a second queue, which runs them in order too, since
+// neovim won't answer them until it gets more input.
+async fn execute_ui_commands(
+    ui_command_receiver: RxUnbounded<UiCommand>,
+    current_nvim: Arc<Mutex<Option<Arc<Neovim<TxWrapper>>>>>,
+    grid_size: Arc<Mutex<(u64, u64)>>,
+    running: Arc<AtomicBool>,
+) {
+    let (blocking_command_sender, blocking_command_receiver) = unbounded_future();
+    tokio::spawn(execute_blocking_ui_commands(blocking_command_receiver));
+
+    while running.load(Ordering::Relaxed) {
+        let mut ui_commands = match ui_command_receiver.recv().await {
+            Ok(ui_command) => vec![ui_command],
+            Err(_) => {
+                running.store(false, Ordering::Relaxed);
+                break;
+            }
+        };
+        while let Ok(ui_command) = ui_command_receiver.try_recv() {
+            ui_commands.push(ui_command);
+        }
+
+        for ui_command in coalesce_ui_commands(ui_commands) {
+            if let UiCommand::Resize { width, height } = ui_command {
+                *grid_size.lock() = (width as u64, height as u64);
+            }
+
+            // Commands sent while disconnected are dropped. The reattach picks up the latest grid
+            // size, so nothing else needs to be replayed.
+            let input_nvim = current_nvim.lock().clone();
+            if let Some(input_nvim) = input_nvim {
+                if ui_command.may_block() {
+                    blocking_command_sender.send((ui_command, input_nvim)).ok();
+                } else {
+                    execute_ui_command(ui_command, &input_nvim).await;
+                }
+            }
+        }
+    }
+}
+
+// Stops once execute_ui_commands drops the sender
+async fn execute_blocking_ui_commands(
+    blocking_command_receiver: RxUnbounded<(UiCommand, Arc<Neovim<TxWrapper>>)>,
+) {
+    while let Ok((ui_command, nvim)) = blocking_command_receiver.recv().await {
+        execute_ui_command(ui_command, &nvim).await;
+    }
+}
+
+async fn execute_ui_command(ui_command: UiCommand, nvim: &Neovim<TxWrapper>) {
+    // The error names the rpc call which failed
+    if let Err(error) = ui_command.execute(nvim).await {
+        error!("Could not send ui command to neovim: {}", error);
+    }
 }
 
 pub struct Bridge {
@@ -324,14 +781,16 @@ pub struct Bridge {
 pub fn start_bridge(
     ui_command_sender: LoggingTx<UiCommand>,
     ui_command_receiver: RxUnbounded<UiCommand>,
-    redraw_event_sender: LoggingTx<RedrawEvent>,
+    editor_command_sender: LoggingTx<EditorCommand>,
+    window_command_sender: LoggingSender<WindowCommand>,
     running: Arc<AtomicBool>,
 ) -> Bridge {
     let runtime = Runtime::new().unwrap();
     runtime.spawn(start_neovim_runtime(
         ui_command_sender,
         ui_command_receiver,
-        redraw_event_sender,
+        editor_command_sender,
+        window_command_sender,
         running,
     ));
     Bridge { _runtime: runtime }
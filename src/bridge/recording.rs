@@ -0,0 +1,204 @@
+//! Recording and replaying of the raw redraw notifications sent by neovim. A recording is a
+//! stream of msgpack arrays of the form `[microseconds_since_start, redraw_arguments]` so that a
+//! session can be fed back into the editor with its original timing and without a neovim process.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossfire::mpsc::RxUnbounded;
+use log::{error, info, warn};
+use rmpv::decode::read_value;
+use rmpv::encode::write_value;
+use rmpv::Value;
+
+use super::events::parse_redraw_event;
+use super::ui_commands::UiCommand;
+use crate::channel_utils::*;
+use crate::editor::EditorCommand;
+
+pub struct RedrawRecorder {
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+}
+
+impl RedrawRecorder {
+    pub fn new(writer: Box<dyn Write + Send>) -> RedrawRecorder {
+        RedrawRecorder {
+            writer,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn create(path: &str) -> io::Result<RedrawRecorder> {
+        let file = File::create(path)?;
+        Ok(RedrawRecorder::new(Box::new(BufWriter::new(file))))
+    }
+
+    pub fn record(&mut self, arguments: &[Value]) -> io::Result<()> {
+        let timestamp = self.start.elapsed().as_micros() as u64;
+        let entry = Value::Array(vec![
+            Value::from(timestamp),
+            Value::Array(arguments.to_vec()),
+        ]);
+
+        write_value(&mut self.writer, &entry)
+            .map_err(|error| io::Error::new(ErrorKind::Other, error.to_string()))?;
+        // Flush every notification so that the recording is usable even if neovide crashes,
+        // which is usually when it is wanted most.
+        self.writer.flush()
+    }
+}
+
+#[derive(Debug)]
+pub struct RecordedRedraw {
+    pub timestamp: Duration,
+    pub arguments: Vec<Value>,
+}
+
+fn parse_recorded_redraw(entry: Value) -> io::Result<RecordedRedraw> {
+    if let Value::Array(values) = &entry {
+        if let [timestamp, Value::Array(arguments)] = values.as_slice() {
+            if let Some(timestamp) = timestamp.as_u64() {
+                return Ok(RecordedRedraw {
+                    timestamp: Duration::from_micros(timestamp),
+                    arguments: arguments.clone(),
+                });
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid recording entry {}", entry),
+    ))
+}
+
+pub fn read_recording<R: BufRead>(reader: &mut R) -> io::Result<Vec<RecordedRedraw>> {
+    let mut recording = Vec::new();
+
+    // Check for the end up front so that a recording cut off in the middle of an entry is
+    // reported instead of silently dropped
+    while !reader.fill_buf()?.is_empty() {
+        let entry = read_value(reader)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error.to_string()))?;
+        recording.push(parse_recorded_redraw(entry)?);
+    }
+
+    Ok(recording)
+}
+
+pub fn start_replay(
+    path: String,
+    editor_command_sender: LoggingTx<EditorCommand>,
+    ui_command_receiver: RxUnbounded<UiCommand>,
+    running: Arc<AtomicBool>,
+) {
+    // There is no neovim to send input to, but the window still expects someone to listen for
+    // it. Quitting is the only command that means anything during a replay.
+    thread::spawn(move || {
+        while let Ok(ui_command) = ui_command_receiver.recv_blocking() {
+            if let UiCommand::Quit = ui_command {
+                running.store(false, Ordering::Relaxed);
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        let recording =
+            match File::open(&path).and_then(|file| read_recording(&mut BufReader::new(file))) {
+                Ok(recording) => recording,
+                Err(error) => {
+                    error!("Could not read recording {}: {}", path, error);
+                    return;
+                }
+            };
+
+        info!(
+            "Replaying {} redraw notifications from {}",
+            recording.len(),
+            path
+        );
+        let start = Instant::now();
+        for RecordedRedraw {
+            timestamp,
+            arguments,
+        } in recording
+        {
+            if let Some(remaining) = timestamp.checked_sub(start.elapsed()) {
+                thread::sleep(remaining);
+            }
+
+            for events in arguments {
+                let (parsed_events, errors) = parse_redraw_event(events);
+                for error in errors {
+                    warn!("Skipping recorded event {}", error);
+                }
+
+                for parsed_event in parsed_events {
+                    editor_command_sender
+                        .send(EditorCommand::NeovimRedrawEvent(parsed_event))
+                        .ok();
+                }
+            }
+        }
+        info!("Replay finished");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn recording_round_trips() {
+        let buffer = SharedBuffer(Arc::new(Mutex::new(Vec::new())));
+        let mut recorder = RedrawRecorder::new(Box::new(buffer.clone()));
+
+        let flush = Value::Array(vec![Value::from("flush"), Value::Array(vec![])]);
+        let title = Value::Array(vec![
+            Value::from("set_title"),
+            Value::Array(vec![Value::from("neovide")]),
+        ]);
+        recorder.record(&[title.clone()]).unwrap();
+        recorder.record(&[flush.clone()]).unwrap();
+
+        let bytes = buffer.0.lock().unwrap().clone();
+        let recording = read_recording(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(recording.len(), 2);
+        assert_eq!(recording[0].arguments, vec![title]);
+        assert_eq!(recording[1].arguments, vec![flush]);
+        assert!(recording[0].timestamp <= recording[1].timestamp);
+    }
+
+    #[test]
+    fn truncated_recording_is_an_error() {
+        let mut bytes = Vec::new();
+        write_value(
+            &mut bytes,
+            &Value::Array(vec![Value::from(1u64), Value::Array(vec![])]),
+        )
+        .unwrap();
+        bytes.push(0x92); // Start of another two element array
+
+        assert!(read_recording(&mut bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use std::path::{Path, PathBuf};
+
+use log::{error, info, warn};
+use rmpv::Value;
+
+use super::ui_commands::{OpenMode, UiCommand};
+use crate::channel_utils::*;
+use crate::cmd_line::CmdLineSettings;
+use crate::editor::WindowCommand;
+use crate::settings::*;
+
+#[derive(SettingGroup)]
+#[setting_prefix = "single_instance"]
+#[derive(Clone)]
+pub struct SingleInstanceSettings {
+    open_mode: OpenMode,
+}
+
+impl Default for SingleInstanceSettings {
+    fn default() -> Self {
+        SingleInstanceSettings {
+            open_mode: OpenMode::Tab,
+        }
+    }
+}
+
+// What a later invocation asks the running instance to open
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenRequest {
+    pub cwd: String,
+    pub files: Vec<String>,
+    pub neovim_args: Vec<String>,
+}
+
+fn string_list(values: &[String]) -> Value {
+    Value::from(
+        values
+            .iter()
+            .map(|value| Value::from(value.as_str()))
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn parse_string_list(value: &Value) -> Option<Vec<String>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|value| value.as_str().map(str::to_owned))
+        .collect()
+}
+
+// Neovim's startup arguments can't be applied to a running instance, except for the commands to
+// run once the files are open
+fn startup_commands(neovim_args: &[String]) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut arguments = neovim_args.iter();
+    while let Some(argument) = arguments.next() {
+        if argument == "-c" {
+            commands.extend(arguments.next().cloned());
+        } else if let Some(command) = argument.strip_prefix('+') {
+            // A lone + jumps to the end of the file
+            let command = if command.is_empty() { "$" } else { command };
+            commands.push(command.to_owned());
+        } else {
+            info!(
+                "Ignoring neovim argument {} sent to the running instance",
+                argument
+            );
+        }
+    }
+    commands
+}
+
+impl OpenRequest {
+    pub fn from_command_line() -> OpenRequest {
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        let cwd = std::env::current_dir()
+            .map(|cwd| cwd.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        OpenRequest {
+            cwd,
+            files: settings.files_to_open,
+            neovim_args: settings.neovim_args,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Map(vec![
+            (Value::from("cwd"), Value::from(self.cwd.as_str())),
+            (Value::from("files"), string_list(&self.files)),
+            (Value::from("neovim_args"), string_list(&self.neovim_args)),
+        ])
+    }
+
+    pub fn from_value(value: Value) -> Option<OpenRequest> {
+        let mut request = OpenRequest {
+            cwd: String::new(),
+            files: Vec::new(),
+            neovim_args: Vec::new(),
+        };
+
+        for (key, value) in value.as_map()? {
+            match key.as_str()? {
+                "cwd" => request.cwd = value.as_str()?.to_owned(),
+                "files" => request.files = parse_string_list(value)?,
+                "neovim_args" => request.neovim_args = parse_string_list(value)?,
+                _ => {}
+            }
+        }
+
+        Some(request)
+    }
+
+    // Files are relative to where the later invocation ran, not to the running instance
+    pub fn into_ui_command(self, mode: OpenMode) -> UiCommand {
+        let cwd = Path::new(&self.cwd);
+        let files = self
+            .files
+            .iter()
+            .map(|file| cwd.join(file).to_string_lossy().into_owned())
+            .collect();
+
+        UiCommand::OpenFiles {
+            files,
+            mode,
+            commands: startup_commands(&self.neovim_args),
+        }
+    }
+}
+
+// The socket is per user so that instances started by different users never see each other.
+// XDG_RUNTIME_DIR is private to its user already, in the shared temp directory the socket gets a
+// private directory of its own.
+fn socket_path() -> PathBuf {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(directory) => PathBuf::from(directory).join(format!("neovide-{}.sock", user)),
+        None => std::env::temp_dir()
+            .join(format!("neovide-{}", user))
+            .join("neovide.sock"),
+    }
+}
+
+// Creates the directory of the socket if needed and makes sure no other user can reach into it.
+// A directory someone else created in its place either lets others in, which is refused here, or
+// can't be entered at all, in which case connecting and listening fail.
+#[cfg(unix)]
+fn private_socket_directory(path: &Path) -> std::io::Result<()> {
+    use std::fs::{self, DirBuilder};
+    use std::io::{Error, ErrorKind};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let directory = path.parent().unwrap_or_else(|| Path::new("/"));
+    match DirBuilder::new().mode(0o700).create(directory) {
+        Err(error) if error.kind() != ErrorKind::AlreadyExists => return Err(error),
+        _ => {}
+    }
+
+    let metadata = fs::symlink_metadata(directory)?;
+    if !metadata.is_dir() || metadata.permissions().mode() & 0o077 != 0 {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} is reachable by other users", directory.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+// Hands the files of this invocation to a running instance. Returns false if there is none, in
+// which case this instance starts normally and becomes the one others forward to.
+#[cfg(unix)]
+pub fn forward_to_running_instance() -> bool {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    let path = socket_path();
+    if let Err(error) = private_socket_directory(&path) {
+        warn!("Not looking for a running instance: {}", error);
+        return false;
+    }
+
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(_) => return false,
+    };
+
+    let mut request = Vec::new();
+    rmpv::encode::write_value(&mut request, &OpenRequest::from_command_line().to_value())
+        .expect("Could not encode open request");
+    match stream.write_all(&request) {
+        Ok(()) => {
+            info!("Forwarded files to the running instance");
+            true
+        }
+        Err(error) => {
+            warn!(
+                "Could not reach the running instance, starting a new one: {}",
+                error
+            );
+            false
+        }
+    }
+}
+
+// Named pipes are not available with the version of tokio we depend on, so every invocation
+// opens its own window outside of unix
+#[cfg(not(unix))]
+pub fn forward_to_running_instance() -> bool {
+    warn!("Single instance mode is not supported on this platform");
+    false
+}
+
+// Listens for later invocations and opens what they send in this instance
+#[cfg(unix)]
+pub fn start_single_instance_listener(
+    ui_command_sender: LoggingTx<UiCommand>,
+    window_command_sender: LoggingSender<WindowCommand>,
+) {
+    use std::fs::{self, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+
+    use tokio::io::AsyncReadExt;
+    use tokio::net::UnixListener;
+
+    let path = socket_path();
+    if let Err(error) = private_socket_directory(&path) {
+        error!("Could not listen for other instances: {}", error);
+        return;
+    }
+
+    // A socket that still answers belongs to another instance, one that doesn't was left behind
+    // by an instance that crashed
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            warn!(
+                "Another instance is already listening on {}",
+                path.display()
+            );
+            return;
+        }
+        fs::remove_file(&path).ok();
+    }
+
+    let mut listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(error) => {
+            error!("Could not listen on {}: {}", path.display(), error);
+            return;
+        }
+    };
+    fs::set_permissions(&path, Permissions::from_mode(0o600)).ok();
+    info!("Listening for other instances on {}", path.display());
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(error) => {
+                    error!(
+                        "Could not accept connection from another instance: {}",
+                        error
+                    );
+                    break;
+                }
+            };
+
+            let mut request = Vec::new();
+            if let Err(error) = stream.read_to_end(&mut request).await {
+                warn!("Could not read request from another instance: {}", error);
+                continue;
+            }
+            // Other instances checking whether the socket is alive connect without sending
+            if request.is_empty() {
+                continue;
+            }
+
+            match rmpv::decode::read_value(&mut request.as_slice())
+                .ok()
+                .and_then(OpenRequest::from_value)
+            {
+                Some(request) => {
+                    let mode = SETTINGS.get::<SingleInstanceSettings>().open_mode;
+                    ui_command_sender.send(request.into_ui_command(mode)).ok();
+                    window_command_sender.send(WindowCommand::FocusWindow).ok();
+                }
+                None => warn!("Ignoring malformed request from another instance"),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn start_single_instance_listener(
+    _ui_command_sender: LoggingTx<UiCommand>,
+    _window_command_sender: LoggingSender<WindowCommand>,
+) {
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_survive_encoding() {
+        let request = OpenRequest {
+            cwd: "/home/user/project".to_owned(),
+            files: vec!["src/main.rs".to_owned(), "/etc/hosts".to_owned()],
+            neovim_args: vec!["+12".to_owned()],
+        };
+
+        assert_eq!(OpenRequest::from_value(request.to_value()), Some(request));
+        assert_eq!(OpenRequest::from_value(Value::from("files")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn requests_open_files_relative_to_their_cwd() {
+        let request = OpenRequest {
+            cwd: "/home/user/project".to_owned(),
+            files: vec!["src/main.rs".to_owned(), "/etc/hosts".to_owned()],
+            neovim_args: vec![
+                "+".to_owned(),
+                "-c".to_owned(),
+                "set number".to_owned(),
+                "-d".to_owned(),
+            ],
+        };
+
+        match request.into_ui_command(OpenMode::Split) {
+            UiCommand::OpenFiles {
+                files,
+                mode,
+                commands,
+            } => {
+                assert_eq!(files, vec!["/home/user/project/src/main.rs", "/etc/hosts"]);
+                assert_eq!(mode, OpenMode::Split);
+                assert_eq!(commands, vec!["$", "set number"]);
+            }
+            command => panic!("Unexpected command {:?}", command),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shared_socket_directories_are_refused() {
+        use std::fs::{self, Permissions};
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = std::env::temp_dir().join(format!("neovide-test-{}", std::process::id()));
+        let path = directory.join("neovide.sock");
+        private_socket_directory(&path).unwrap();
+        let mode = fs::metadata(&directory).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        fs::set_permissions(&directory, Permissions::from_mode(0o755)).unwrap();
+        assert!(private_socket_directory(&path).is_err());
+        fs::remove_dir(&directory).unwrap();
+    }
+}
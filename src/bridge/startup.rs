@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use log::warn;
+use parking_lot::Mutex;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::ChildStderr;
+use tokio::task::JoinHandle;
+
+// How much of neovim's stderr is kept around to explain a failed start
+const STDERR_LINE_COUNT: usize = 20;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StartupFailureReason {
+    NotFound,
+    BadPermissions,
+    SpawnFailed(String),
+    ConnectionFailed(String),
+    NoResponse(String),
+    UnsupportedVersion(String),
+    AttachFailed(String),
+}
+
+impl StartupFailureReason {
+    pub fn from_spawn_error(error: &io::Error) -> StartupFailureReason {
+        match error.kind() {
+            io::ErrorKind::NotFound => StartupFailureReason::NotFound,
+            io::ErrorKind::PermissionDenied => StartupFailureReason::BadPermissions,
+            _ => StartupFailureReason::SpawnFailed(error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for StartupFailureReason {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupFailureReason::NotFound => write!(
+                formatter,
+                "nvim was not found. Install neovim, add it to the PATH or point NEOVIM_BIN at it."
+            ),
+            StartupFailureReason::BadPermissions => {
+                write!(formatter, "nvim exists but could not be executed")
+            }
+            StartupFailureReason::SpawnFailed(error) => {
+                write!(formatter, "nvim could not be started: {}", error)
+            }
+            StartupFailureReason::ConnectionFailed(error) => {
+                write!(formatter, "Could not connect to neovim: {}", error)
+            }
+            StartupFailureReason::NoResponse(error) => write!(
+                formatter,
+                "Neovim exited or stopped responding while starting: {}",
+                error
+            ),
+            StartupFailureReason::UnsupportedVersion(version) => write!(
+                formatter,
+                "Neovide requires nvim version 0.4 or higher, found {}",
+                version
+            ),
+            StartupFailureReason::AttachFailed(error) => {
+                write!(formatter, "Could not attach to neovim: {}", error)
+            }
+        }
+    }
+}
+
+// Everything known about a failed start, for the error screen
+#[derive(Clone, Debug, PartialEq)]
+pub struct StartupFailure {
+    pub reason: StartupFailureReason,
+    // The command neovim was started with, or the address neovide tried to connect to
+    pub command: String,
+    pub stderr: Vec<String>,
+}
+
+// The last lines neovim wrote to stderr. Neovim's stderr is piped, so it has to be read even when
+// nobody looks at it, otherwise neovim blocks once the pipe fills up.
+#[derive(Clone, Default)]
+pub struct StderrLog {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl StderrLog {
+    pub fn push(&self, line: String) {
+        let mut lines = self.lines.lock();
+        if lines.len() == STDERR_LINE_COUNT {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().iter().cloned().collect()
+    }
+
+    // Reads stderr until neovim closes it
+    pub fn capture(&self, stderr: ChildStderr) -> JoinHandle<()> {
+        let log = self.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            loop {
+                match lines.next_line().await {
+                    Ok(Some(line)) => {
+                        warn!("nvim: {}", line);
+                        log.push(line);
+                    }
+                    Ok(None) => break,
+                    Err(error) => {
+                        warn!("Could not read neovim's stderr: {}", error);
+                        break;
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stderr_log_keeps_the_last_lines() {
+        let log = StderrLog::default();
+        for index in 0..STDERR_LINE_COUNT + 5 {
+            log.push(index.to_string());
+        }
+
+        let lines = log.lines();
+        assert_eq!(lines.len(), STDERR_LINE_COUNT);
+        assert_eq!(lines[0], "5");
+        assert_eq!(lines.last().unwrap(), &(STDERR_LINE_COUNT + 4).to_string());
+    }
+
+    #[test]
+    fn spawn_errors_map_to_reasons() {
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "missing");
+        assert_eq!(
+            StartupFailureReason::from_spawn_error(&not_found),
+            StartupFailureReason::NotFound
+        );
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert_eq!(
+            StartupFailureReason::from_spawn_error(&denied),
+            StartupFailureReason::BadPermissions
+        );
+    }
+}
@@ -8,10 +8,15 @@ use tokio::{
     process::ChildStdin,
 };
 
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
 #[pin_project(project = TxProj)]
 pub enum TxWrapper {
     Child(#[pin] ChildStdin),
     Tcp(#[pin] WriteHalf<TcpStream>),
+    #[cfg(unix)]
+    Unix(#[pin] WriteHalf<UnixStream>),
 }
 
 impl futures::io::AsyncWrite for TxWrapper {
@@ -23,6 +28,8 @@ impl futures::io::AsyncWrite for TxWrapper {
         match self.project() {
             TxProj::Child(inner) => inner.poll_write(cx, buf),
             TxProj::Tcp(inner) => inner.poll_write(cx, buf),
+            #[cfg(unix)]
+            TxProj::Unix(inner) => inner.poll_write(cx, buf),
         }
     }
 
@@ -30,6 +37,8 @@ impl futures::io::AsyncWrite for TxWrapper {
         match self.project() {
             TxProj::Child(inner) => inner.poll_flush(cx),
             TxProj::Tcp(inner) => inner.poll_flush(cx),
+            #[cfg(unix)]
+            TxProj::Unix(inner) => inner.poll_flush(cx),
         }
     }
 
@@ -37,6 +46,8 @@ impl futures::io::AsyncWrite for TxWrapper {
         match self.project() {
             TxProj::Child(inner) => inner.poll_shutdown(cx),
             TxProj::Tcp(inner) => inner.poll_shutdown(cx),
+            #[cfg(unix)]
+            TxProj::Unix(inner) => inner.poll_shutdown(cx),
         }
     }
 }
@@ -56,3 +67,10 @@ impl WrapTx for WriteHalf<TcpStream> {
         TxWrapper::Tcp(self)
     }
 }
+
+#[cfg(unix)]
+impl WrapTx for WriteHalf<UnixStream> {
+    fn wrap_tx(self) -> TxWrapper {
+        TxWrapper::Unix(self)
+    }
+}
@@ -1,17 +1,79 @@
-use log::trace;
+use log::{error, trace};
 
-#[cfg(windows)]
-use log::error;
-
-use nvim_rs::Neovim;
+use nvim_rs::error::CallError;
+use nvim_rs::{Neovim, Tabpage};
+use rmpv::Value;
 
 use crate::bridge::TxWrapper;
+use crate::settings::FromValue;
 
 #[cfg(windows)]
 use crate::windows_utils::{
     register_rightclick_directory, register_rightclick_file, unregister_rightclick,
 };
 
+// Where opened files go relative to the current window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpenMode {
+    Edit,
+    Split,
+    Vsplit,
+    Tab,
+    // Replaces the argument list with the files
+    Args,
+}
+
+impl OpenMode {
+    // Builds one command opening all of the already escaped files
+    fn open_command(self, files: &[String]) -> String {
+        let command = match self {
+            OpenMode::Edit => "edit",
+            OpenMode::Split => "split",
+            OpenMode::Vsplit => "vsplit",
+            OpenMode::Tab => "tabedit",
+            OpenMode::Args => return format!("args {}", files.join(" ")),
+        };
+
+        files
+            .iter()
+            .map(|file| format!("{} {}", command, file))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+impl FromValue for OpenMode {
+    fn from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "edit" => OpenMode::Edit,
+                "split" => OpenMode::Split,
+                "vsplit" => OpenMode::Vsplit,
+                "tab" => OpenMode::Tab,
+                "args" => OpenMode::Args,
+                value => {
+                    error!("Expected an open mode name, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!("Expected an open mode string, but received {:?}", value);
+        }
+    }
+}
+
+impl From<OpenMode> for Value {
+    fn from(mode: OpenMode) -> Self {
+        match mode {
+            OpenMode::Edit => Value::from("edit"),
+            OpenMode::Split => Value::from("split"),
+            OpenMode::Vsplit => Value::from("vsplit"),
+            OpenMode::Tab => Value::from("tab"),
+            OpenMode::Args => Value::from("args"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum UiCommand {
     Quit,
@@ -34,9 +96,33 @@ pub enum UiCommand {
         grid_id: u64,
         position: (u32, u32),
     },
-    FileDrop(String),
+    // The window is the neovim window under the drop. Without multigrid only the screen
+    // position is known, in cells, and the window is looked up from it.
+    FileDrop {
+        files: Vec<String>,
+        mode: OpenMode,
+        window: Option<u64>,
+        position: (u32, u32),
+    },
+    // Files sent by another invocation in single instance mode, followed by the commands to run
+    // once they are open
+    OpenFiles {
+        files: Vec<String>,
+        mode: OpenMode,
+        commands: Vec<String>,
+    },
+    SelectPopupMenuItem {
+        item: u64,
+        insert: bool,
+        finish: bool,
+    },
+    // Tabs are identified by the handles from tabline_update, which stay the same when tabs are
+    // moved around
+    SwitchTab(Value),
+    CloseTab(Value),
     FocusLost,
     FocusGained,
+    Redraw,
     #[cfg(windows)]
     RegisterRightClick,
     #[cfg(windows)]
@@ -44,18 +130,33 @@ pub enum UiCommand {
 }
 
 impl UiCommand {
-    pub async fn execute(self, nvim: &Neovim<TxWrapper>) {
+    // Focus autocommands run user code, and opening files can stop at the swap file prompt. Neovim
+    // doesn't answer these until the prompt is dismissed, so they must not hold up the input which
+    // would dismiss it.
+    pub fn may_block(&self) -> bool {
+        matches!(
+            self,
+            UiCommand::FocusLost
+                | UiCommand::FocusGained
+                | UiCommand::FileDrop { .. }
+                | UiCommand::OpenFiles { .. }
+        )
+    }
+
+    // Failures which only mean neovim moved on, such as a popup menu closing before the selection
+    // arrived, are ignored. Everything else is returned for the caller to report.
+    pub async fn execute(self, nvim: &Neovim<TxWrapper>) -> Result<(), Box<CallError>> {
         match self {
             UiCommand::Quit => {
                 nvim.command("qa!").await.ok();
             }
-            UiCommand::Resize { width, height } => nvim
-                .ui_try_resize(width.max(10) as i64, height.max(3) as i64)
-                .await
-                .expect("Resize failed"),
+            UiCommand::Resize { width, height } => {
+                nvim.ui_try_resize(width.max(10) as i64, height.max(3) as i64)
+                    .await?
+            }
             UiCommand::Keyboard(input_command) => {
                 trace!("Keyboard Input Sent: {}", input_command);
-                nvim.input(&input_command).await.expect("Input failed");
+                nvim.input(&input_command).await?;
             }
             UiCommand::MouseButton {
                 action,
@@ -70,8 +171,7 @@ impl UiCommand {
                     grid_y as i64,
                     grid_x as i64,
                 )
-                .await
-                .expect("Mouse Input Failed");
+                .await?;
             }
             UiCommand::Scroll {
                 direction,
@@ -86,8 +186,7 @@ impl UiCommand {
                     grid_y as i64,
                     grid_x as i64,
                 )
-                .await
-                .expect("Mouse Scroll Failed");
+                .await?;
             }
             UiCommand::Drag {
                 grid_id,
@@ -101,19 +200,65 @@ impl UiCommand {
                     grid_y as i64,
                     grid_x as i64,
                 )
-                .await
-                .expect("Mouse Drag Failed");
-            }
-            UiCommand::FocusLost => nvim
-                .command("if exists('#FocusLost') | doautocmd <nomodeline> FocusLost | endif")
-                .await
-                .expect("Focus Lost Failed"),
-            UiCommand::FocusGained => nvim
-                .command("if exists('#FocusGained') | doautocmd <nomodeline> FocusGained | endif")
-                .await
-                .expect("Focus Gained Failed"),
-            UiCommand::FileDrop(path) => {
-                nvim.command(format!("e {}", path).as_str()).await.ok();
+                .await?;
+            }
+            UiCommand::SelectPopupMenuItem {
+                item,
+                insert,
+                finish,
+            } => {
+                // Fails harmlessly if the menu was closed before the command arrived
+                nvim.select_popupmenu_item(item as i64, insert, finish, vec![])
+                    .await
+                    .ok();
+            }
+            UiCommand::SwitchTab(tab) => {
+                let tabpage = Tabpage::new(tab, nvim.clone());
+                nvim.set_current_tabpage(&tabpage).await.ok();
+            }
+            UiCommand::CloseTab(tab) => {
+                let tabpage = Tabpage::new(tab, nvim.clone());
+                if let Ok(number) = tabpage.get_number().await {
+                    nvim.command(&format!("{}tabclose", number)).await.ok();
+                }
+            }
+            UiCommand::FocusLost => {
+                nvim.command("if exists('#FocusLost') | doautocmd <nomodeline> FocusLost | endif")
+                    .await?
+            }
+            UiCommand::FocusGained => {
+                nvim.command(
+                    "if exists('#FocusGained') | doautocmd <nomodeline> FocusGained | endif",
+                )
+                .await?
+            }
+            UiCommand::Redraw => nvim.command("redraw!").await?,
+            UiCommand::FileDrop {
+                files,
+                mode,
+                window,
+                position,
+            } => {
+                match window {
+                    Some(window) => {
+                        nvim.call_function("win_gotoid", vec![Value::from(window)])
+                            .await?;
+                    }
+                    None => focus_window_at(nvim, position).await?,
+                }
+                let files = escape_file_names(nvim, files).await?;
+                nvim.command(&mode.open_command(&files)).await?;
+            }
+            UiCommand::OpenFiles {
+                files,
+                mode,
+                commands,
+            } => {
+                let files = escape_file_names(nvim, files).await?;
+                nvim.command(&mode.open_command(&files)).await?;
+                for command in commands {
+                    nvim.command(&command).await?;
+                }
             }
             #[cfg(windows)]
             UiCommand::RegisterRightClick => {
@@ -142,5 +287,212 @@ impl UiCommand {
                 }
             }
         }
+
+        Ok(())
+    }
+}
+
+// Escapes all of the files in one request, so that spaces, % and # are taken literally
+async fn escape_file_names(
+    nvim: &Neovim<TxWrapper>,
+    files: Vec<String>,
+) -> Result<Vec<String>, Box<CallError>> {
+    let files = files.into_iter().map(Value::from).collect::<Vec<_>>();
+    let escaped = nvim
+        .call_function(
+            "map",
+            vec![Value::from(files), Value::from("fnameescape(v:val)")],
+        )
+        .await?;
+
+    Ok(escaped
+        .as_array()
+        .map(|files| {
+            files
+                .iter()
+                .filter_map(|file| file.as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+fn info_field<'a>(info: &'a Value, name: &str) -> Option<&'a Value> {
+    info.as_map()?
+        .iter()
+        .find(|(key, _)| key.as_str() == Some(name))
+        .map(|(_, value)| value)
+}
+
+// Whether a window covers a position along one axis. Positions in getwininfo() start at 1.
+fn info_contains(info: &Value, start: &str, size: &str, position: u32) -> bool {
+    let start = info_field(info, start).and_then(Value::as_u64);
+    let size = info_field(info, size).and_then(Value::as_u64);
+    match (start, size) {
+        (Some(start), Some(size)) => {
+            let position = position as u64 + 1;
+            position >= start && position < start + size
+        }
+        _ => false,
+    }
+}
+
+// Finds the window covering a screen position in the output of getwininfo(). Floating windows
+// come after the regular ones, so the last match is the one drawn on top.
+fn window_at(window_infos: &Value, (column, row): (u32, u32)) -> Option<Value> {
+    window_infos
+        .as_array()?
+        .iter()
+        .filter(|info| {
+            info_contains(info, "wincol", "width", column)
+                && info_contains(info, "winrow", "height", row)
+        })
+        .last()
+        .and_then(|info| info_field(info, "winid").cloned())
+}
+
+// Makes the window under a drop the current one, so that what was dropped ends up there. Only
+// needed without multigrid, where every window is drawn on the same grid.
+async fn focus_window_at(
+    nvim: &Neovim<TxWrapper>,
+    position: (u32, u32),
+) -> Result<(), Box<CallError>> {
+    // Only the windows of the current tab, floating ones included
+    let window_infos = nvim
+        .eval("map(range(1, winnr('$')), 'getwininfo(win_getid(v:val))[0]')")
+        .await?;
+
+    if let Some(window) = window_at(&window_infos, position) {
+        nvim.call_function("win_gotoid", vec![window]).await?;
+    }
+
+    Ok(())
+}
+
+// Folds a batch of queued commands into as few rpc calls as possible without changing their
+// effect. Runs of keyboard input become one nvim_input, and only the last of a run of resizes or
+// drags matters.
+pub fn coalesce_ui_commands(commands: impl IntoIterator<Item = UiCommand>) -> Vec<UiCommand> {
+    let mut coalesced: Vec<UiCommand> = Vec::new();
+
+    for command in commands {
+        match (coalesced.last_mut(), command) {
+            (Some(UiCommand::Keyboard(previous_input)), UiCommand::Keyboard(input)) => {
+                previous_input.push_str(&input);
+            }
+            (Some(previous @ UiCommand::Resize { .. }), command @ UiCommand::Resize { .. }) => {
+                *previous = command;
+            }
+            (Some(previous @ UiCommand::Drag { .. }), command @ UiCommand::Drag { .. }) => {
+                *previous = command;
+            }
+            (_, command) => coalesced.push(command),
+        }
+    }
+
+    coalesced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drag(grid_id: u64, position: (u32, u32)) -> UiCommand {
+        UiCommand::Drag { grid_id, position }
+    }
+
+    fn window_info(winid: u64, (wincol, winrow): (u64, u64), (width, height): (u64, u64)) -> Value {
+        Value::Map(vec![
+            (Value::from("winid"), Value::from(winid)),
+            (Value::from("wincol"), Value::from(wincol)),
+            (Value::from("winrow"), Value::from(winrow)),
+            (Value::from("width"), Value::from(width)),
+            (Value::from("height"), Value::from(height)),
+        ])
+    }
+
+    #[test]
+    fn drops_target_the_topmost_window() {
+        let window_infos = Value::from(vec![
+            window_info(1000, (1, 1), (40, 20)),
+            window_info(1001, (42, 1), (39, 20)),
+            window_info(1002, (30, 5), (20, 5)),
+        ]);
+
+        assert_eq!(window_at(&window_infos, (0, 0)), Some(Value::from(1000)));
+        assert_eq!(window_at(&window_infos, (41, 19)), Some(Value::from(1001)));
+        assert_eq!(window_at(&window_infos, (35, 6)), Some(Value::from(1002)));
+        // The separator between the splits
+        assert_eq!(window_at(&window_infos, (40, 0)), None);
+    }
+
+    #[test]
+    fn dropped_files_open_in_one_command() {
+        let files = vec!["a".to_owned(), "b\\ c".to_owned()];
+        assert_eq!(
+            OpenMode::Vsplit.open_command(&files),
+            "vsplit a | vsplit b\\ c"
+        );
+        assert_eq!(OpenMode::Args.open_command(&files), "args a b\\ c");
+    }
+
+    #[test]
+    fn only_prompting_commands_leave_the_input_queue() {
+        assert!(!UiCommand::Keyboard("<Esc>".to_owned()).may_block());
+        assert!(!drag(1, (0, 0)).may_block());
+        assert!(!UiCommand::SwitchTab(Value::from(1)).may_block());
+        assert!(!UiCommand::Redraw.may_block());
+        assert!(UiCommand::FocusGained.may_block());
+        assert!(UiCommand::OpenFiles {
+            files: vec!["a".to_owned()],
+            mode: OpenMode::Edit,
+            commands: Vec::new(),
+        }
+        .may_block());
+    }
+
+    #[test]
+    fn runs_are_coalesced_in_order() {
+        let commands = vec![
+            UiCommand::Keyboard("a".to_owned()),
+            UiCommand::Keyboard("<C-w>".to_owned()),
+            UiCommand::Resize {
+                width: 80,
+                height: 24,
+            },
+            UiCommand::Resize {
+                width: 100,
+                height: 30,
+            },
+            UiCommand::Keyboard("b".to_owned()),
+            drag(1, (1, 1)),
+            drag(1, (4, 2)),
+            UiCommand::MouseButton {
+                action: "release".to_owned(),
+                grid_id: 1,
+                position: (4, 2),
+            },
+            drag(1, (5, 2)),
+        ];
+
+        let coalesced = format!("{:?}", coalesce_ui_commands(commands));
+        let expected = format!(
+            "{:?}",
+            vec![
+                UiCommand::Keyboard("a<C-w>".to_owned()),
+                UiCommand::Resize {
+                    width: 100,
+                    height: 30,
+                },
+                UiCommand::Keyboard("b".to_owned()),
+                drag(1, (4, 2)),
+                UiCommand::MouseButton {
+                    action: "release".to_owned(),
+                    grid_id: 1,
+                    position: (4, 2),
+                },
+                drag(1, (5, 2)),
+            ]
+        );
+        assert_eq!(coalesced, expected);
     }
 }
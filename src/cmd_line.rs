@@ -2,38 +2,76 @@ use crate::settings::*;
 
 use clap::{App, Arg};
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderingBackend {
+    Gl,
+    // Renders on the cpu, for machines where OpenGL is missing or broken
+    Software,
+}
+
+fn parse_rendering_backend(backend: Option<String>) -> Result<RenderingBackend, String> {
+    match backend.as_deref() {
+        None | Some("gl") => Ok(RenderingBackend::Gl),
+        Some("software") => Ok(RenderingBackend::Software),
+        Some(backend) => Err(format!(
+            "Invalid renderer: {}\nValid renderers: gl, software",
+            backend
+        )),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct CmdLineSettings {
     pub verbosity: u64,
     pub log_to_file: bool,
     pub neovim_args: Vec<String>,
     pub neovim_bin: Option<String>,
+    pub neovim_command: Option<String>,
     pub files_to_open: Vec<String>,
 
     pub disowned: bool,
+    pub no_fork: bool,
+    pub single_instance: bool,
     pub geometry: WindowGeometry,
     pub wsl: bool,
     pub remote_tcp: Option<String>,
+    pub server: Option<String>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
     pub multi_grid: bool,
+    pub ext_messages: bool,
+    pub ext_tabline: bool,
     pub maximized: bool,
     pub frameless: bool,
+    pub renderer: RenderingBackend,
+    pub offscreen: bool,
 }
 
 impl Default for CmdLineSettings {
     fn default() -> Self {
         Self {
             neovim_bin: None,
+            neovim_command: None,
             verbosity: 0,
             log_to_file: false,
             neovim_args: vec![],
             files_to_open: vec![],
             disowned: false,
+            no_fork: false,
+            single_instance: false,
             geometry: DEFAULT_WINDOW_GEOMETRY,
             wsl: false,
             remote_tcp: None,
+            server: None,
+            record: None,
+            replay: None,
             multi_grid: false,
+            ext_messages: false,
+            ext_tabline: false,
             maximized: false,
             frameless: false,
+            renderer: RenderingBackend::Gl,
+            offscreen: false,
         }
     }
 }
@@ -59,6 +97,16 @@ pub fn handle_command_line_arguments() -> Result<(), String> {
                 .long("disowned")
                 .help("Disown the process. (only on macos)"),
         )
+        .arg(
+            Arg::with_name("no_fork")
+                .long("nofork")
+                .help("Wait until the editor is closed and exit with neovim's exit code"),
+        )
+        .arg(
+            Arg::with_name("single_instance")
+                .long("single-instance")
+                .help("Open files in the already running instance if there is one"),
+        )
         .arg(
             Arg::with_name("maximized")
                 .long("maximized")
@@ -71,18 +119,67 @@ pub fn handle_command_line_arguments() -> Result<(), String> {
                 .long("multiGrid")
                 .help("Enable Multigrid"),
         )
+        .arg(
+            Arg::with_name("ext_messages")
+                .long("ext-messages")
+                .help("Show messages as notifications instead of in the message area"),
+        )
+        .arg(
+            Arg::with_name("ext_tabline")
+                .long("ext-tabline")
+                .help("Draw the tabs in a bar instead of using neovim's tabline"),
+        )
         .arg(
             Arg::with_name("frameless")
             .long("frameless")
             .help("Removes the window frame. NOTE: Window might not be resizable after this setting is enabled.")
         )
         .arg(Arg::with_name("wsl").long("wsl").help("Run in WSL"))
+        .arg(
+            Arg::with_name("renderer")
+                .long("renderer")
+                .takes_value(true)
+                .possible_values(&["gl", "software"])
+                .help("Render with OpenGL or on the cpu. OpenGL falls back to the cpu when it is not available"),
+        )
+        .arg(
+            Arg::with_name("offscreen")
+                .long("offscreen")
+                .help("Render on the cpu without opening a window"),
+        )
+        .arg(
+            Arg::with_name("neovim_command")
+                .long("neovim-command")
+                .takes_value(true)
+                .conflicts_with_all(&["wsl", "remote_tcp", "server"])
+                .help("Start neovim through a command such as \"ssh host\" or \"docker exec -i container\""),
+        )
         .arg(
             Arg::with_name("remote_tcp")
                 .long("remote-tcp")
                 .takes_value(true)
                 .help("Connect to Remote TCP"),
         )
+        .arg(
+            Arg::with_name("server")
+                .long("server")
+                .takes_value(true)
+                .conflicts_with("remote_tcp")
+                .help("Connect to a neovim server listening on a local socket"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .help("Record the redraw events sent by neovim to a file"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .takes_value(true)
+                .conflicts_with_all(&["record", "remote_tcp", "server"])
+                .help("Replay a recording made with --record instead of starting neovim"),
+        )
         .arg(
             Arg::with_name("geometry")
                 .long("geometry")
@@ -109,10 +206,20 @@ pub fn handle_command_line_arguments() -> Result<(), String> {
      * Integrate Environment Variables as Defaults to the command-line ones.
      *
      * NEOVIM_BIN
+     * NEOVIDE_NEOVIM_COMMAND || --neovim-command
      * NeovideMultiGrid || --multiGrid
+     * NEOVIDE_EXT_MESSAGES || --ext-messages
+     * NEOVIDE_EXT_TABLINE || --ext-tabline
+     * NEOVIDE_NOFORK || --nofork
+     * NEOVIDE_SINGLE_INSTANCE || --single-instance
+     * NEOVIDE_RENDERER || --renderer
      */
     SETTINGS.set::<CmdLineSettings>(&CmdLineSettings {
         neovim_bin: std::env::var("NEOVIM_BIN").ok(),
+        neovim_command: matches
+            .value_of("neovim_command")
+            .map(|command| command.to_owned())
+            .or_else(|| std::env::var("NEOVIDE_NEOVIM_COMMAND").ok()),
         neovim_args: matches
             .values_of("neovim_args")
             .map(|opt| opt.map(|v| v.to_owned()).collect())
@@ -127,11 +234,28 @@ pub fn handle_command_line_arguments() -> Result<(), String> {
         multi_grid: std::env::var("NEOVIDE_MULTIGRID").is_ok()
             || std::env::var("NeovideMultiGrid").is_ok()
             || matches.is_present("multi_grid"),
+        ext_messages: std::env::var("NEOVIDE_EXT_MESSAGES").is_ok()
+            || matches.is_present("ext_messages"),
+        ext_tabline: std::env::var("NEOVIDE_EXT_TABLINE").is_ok()
+            || matches.is_present("ext_tabline"),
         remote_tcp: matches.value_of("remote_tcp").map(|i| i.to_owned()),
+        server: matches.value_of("server").map(|i| i.to_owned()),
+        record: matches.value_of("record").map(|i| i.to_owned()),
+        replay: matches.value_of("replay").map(|i| i.to_owned()),
         disowned: matches.is_present("disowned"),
+        no_fork: matches.is_present("no_fork") || std::env::var("NEOVIDE_NOFORK").is_ok(),
+        single_instance: matches.is_present("single_instance")
+            || std::env::var("NEOVIDE_SINGLE_INSTANCE").is_ok(),
         wsl: matches.is_present("wsl"),
         frameless: matches.is_present("frameless") || std::env::var("NEOVIDE_FRAMELESS").is_ok(),
         geometry: parse_window_geometry(matches.value_of("geometry").map(|i| i.to_owned()))?,
+        renderer: parse_rendering_backend(
+            matches
+                .value_of("renderer")
+                .map(|i| i.to_owned())
+                .or_else(|| std::env::var("NEOVIDE_RENDERER").ok()),
+        )?,
+        offscreen: matches.is_present("offscreen"),
     });
     Ok(())
 }
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::grid::GridCell;
+use super::style::Style;
+use crate::bridge::StyledContent;
+
+// Everything the renderer needs to draw the command line. The lines are already split into cells
+// so that the renderer only has to wrap them to the width of the box.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandLineDisplay {
+    pub block_lines: Vec<Vec<GridCell>>,
+    pub line: Vec<GridCell>,
+    pub cursor_column: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+struct CommandLineLevel {
+    content: Vec<GridCell>,
+    position: u64,
+    first_character: String,
+    prompt: String,
+    indent: u64,
+    special_character: Option<(String, bool)>,
+}
+
+impl CommandLineLevel {
+    // Neovim sends the cursor position as a byte offset into the content
+    fn cell_position(&self) -> usize {
+        let mut bytes = 0;
+        self.content
+            .iter()
+            .take_while(|(text, _)| {
+                bytes += text.len() as u64;
+                bytes <= self.position
+            })
+            .count()
+    }
+
+    fn prefix(&self) -> Vec<GridCell> {
+        let mut prefix = unstyled_cells(&self.first_character);
+        prefix.extend(unstyled_cells(&self.prompt));
+        prefix.extend((0..self.indent).map(|_| (" ".to_owned(), None)));
+        prefix
+    }
+}
+
+fn unstyled_cells(text: &str) -> Vec<GridCell> {
+    text.graphemes(true)
+        .map(|character| (character.to_owned(), None))
+        .collect()
+}
+
+pub fn styled_cells(
+    content: &StyledContent,
+    defined_styles: &HashMap<u64, Arc<Style>>,
+) -> Vec<GridCell> {
+    content
+        .iter()
+        .flat_map(|(style_id, text)| {
+            let style = defined_styles.get(style_id).cloned();
+            text.graphemes(true)
+                .map(move |character| (character.to_owned(), style.clone()))
+        })
+        .collect()
+}
+
+// Tracks the externalized command line. Nested command lines (such as the expression register
+// opened with <C-r>=) get their own level, and only the innermost one is shown.
+#[derive(Default)]
+pub struct CommandLine {
+    levels: Vec<CommandLineLevel>,
+    block_lines: Vec<Vec<GridCell>>,
+    dirty: bool,
+}
+
+impl CommandLine {
+    pub fn new() -> CommandLine {
+        CommandLine::default()
+    }
+
+    pub fn show(
+        &mut self,
+        content: Vec<GridCell>,
+        position: u64,
+        first_character: String,
+        prompt: String,
+        indent: u64,
+        level: u64,
+    ) {
+        self.levels.truncate(level.saturating_sub(1) as usize);
+        self.levels.push(CommandLineLevel {
+            content,
+            position,
+            first_character,
+            prompt,
+            indent,
+            special_character: None,
+        });
+        self.dirty = true;
+    }
+
+    pub fn set_position(&mut self, position: u64, level: u64) {
+        if let Some(command_line_level) = self.level_mut(level) {
+            command_line_level.position = position;
+            command_line_level.special_character = None;
+            self.dirty = true;
+        }
+    }
+
+    pub fn set_special_character(&mut self, character: String, shift: bool, level: u64) {
+        if let Some(command_line_level) = self.level_mut(level) {
+            command_line_level.special_character = Some((character, shift));
+            self.dirty = true;
+        }
+    }
+
+    pub fn hide(&mut self) {
+        self.levels.pop();
+        self.dirty = true;
+    }
+
+    pub fn show_block(&mut self, lines: Vec<Vec<GridCell>>) {
+        self.block_lines = lines;
+        self.dirty = true;
+    }
+
+    pub fn append_block(&mut self, line: Vec<GridCell>) {
+        self.block_lines.push(line);
+        self.dirty = true;
+    }
+
+    pub fn hide_block(&mut self) {
+        self.block_lines.clear();
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.levels.clear();
+        self.block_lines.clear();
+        self.dirty = true;
+    }
+
+    // Returns true once for every batch of changes
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    fn level_mut(&mut self, level: u64) -> Option<&mut CommandLineLevel> {
+        self.levels.get_mut(level.checked_sub(1)? as usize)
+    }
+
+    pub fn display(&self) -> Option<CommandLineDisplay> {
+        let level = self.levels.last()?;
+
+        let mut line = level.prefix();
+        let cursor_column = (line.len() + level.cell_position()) as u64;
+
+        let mut content = level.content.clone();
+        if let Some((character, shift)) = &level.special_character {
+            let position = level.cell_position();
+            let cell = (character.clone(), None);
+            if *shift || position >= content.len() {
+                content.insert(position.min(content.len()), cell);
+            } else {
+                content[position] = cell;
+            }
+        }
+        line.extend(content);
+
+        Some(CommandLineDisplay {
+            block_lines: self.block_lines.clone(),
+            line,
+            cursor_column,
+        })
+    }
+
+    pub fn cursor_character(&self) -> Option<String> {
+        let display = self.display()?;
+        Some(
+            display
+                .line
+                .get(display.cursor_column as usize)
+                .map(|(character, _)| character.clone())
+                .unwrap_or_else(|| " ".to_owned()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_text(cells: &[GridCell]) -> String {
+        cells
+            .iter()
+            .map(|(character, _)| character.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn display_includes_prompt_and_indent() {
+        let mut command_line = CommandLine::new();
+        command_line.show(
+            unstyled_cells("abc"),
+            1,
+            "".to_owned(),
+            "Name: ".to_owned(),
+            2,
+            1,
+        );
+
+        let display = command_line.display().unwrap();
+        assert_eq!(line_text(&display.line), "Name:   abc");
+        assert_eq!(display.cursor_column, 9);
+        assert_eq!(command_line.cursor_character(), Some("b".to_owned()));
+    }
+
+    #[test]
+    fn cursor_position_counts_cells_not_bytes() {
+        let mut command_line = CommandLine::new();
+        command_line.show(
+            unstyled_cells("äöx"),
+            4,
+            ":".to_owned(),
+            "".to_owned(),
+            0,
+            1,
+        );
+
+        let display = command_line.display().unwrap();
+        assert_eq!(display.cursor_column, 3);
+        assert_eq!(command_line.cursor_character(), Some("x".to_owned()));
+    }
+
+    #[test]
+    fn special_character_overlays_or_shifts() {
+        let mut command_line = CommandLine::new();
+        command_line.show(
+            unstyled_cells("abc"),
+            1,
+            ":".to_owned(),
+            "".to_owned(),
+            0,
+            1,
+        );
+
+        command_line.set_special_character("^".to_owned(), false, 1);
+        assert_eq!(line_text(&command_line.display().unwrap().line), ":a^c");
+
+        command_line.set_special_character("\"".to_owned(), true, 1);
+        assert_eq!(line_text(&command_line.display().unwrap().line), ":a\"bc");
+
+        command_line.set_position(2, 1);
+        assert_eq!(line_text(&command_line.display().unwrap().line), ":abc");
+    }
+
+    #[test]
+    fn nested_levels_show_the_innermost() {
+        let mut command_line = CommandLine::new();
+        command_line.show(
+            unstyled_cells("echo "),
+            5,
+            ":".to_owned(),
+            "".to_owned(),
+            0,
+            1,
+        );
+        command_line.show(
+            unstyled_cells("1+1"),
+            3,
+            "=".to_owned(),
+            "".to_owned(),
+            0,
+            2,
+        );
+        assert_eq!(line_text(&command_line.display().unwrap().line), "=1+1");
+
+        command_line.hide();
+        assert_eq!(line_text(&command_line.display().unwrap().line), ":echo ");
+
+        command_line.hide();
+        assert!(command_line.display().is_none());
+    }
+
+    #[test]
+    fn block_lines_are_kept_until_hidden() {
+        let mut command_line = CommandLine::new();
+        command_line.show_block(vec![unstyled_cells("function! Foo()")]);
+        command_line.append_block(unstyled_cells("  echo 1"));
+        command_line.show(unstyled_cells(""), 0, ":".to_owned(), "".to_owned(), 2, 1);
+
+        let display = command_line.display().unwrap();
+        assert_eq!(display.block_lines.len(), 2);
+        assert_eq!(line_text(&display.block_lines[1]), "  echo 1");
+
+        command_line.hide_block();
+        assert!(command_line.display().unwrap().block_lines.is_empty());
+    }
+}
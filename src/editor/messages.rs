@@ -0,0 +1,136 @@
+use super::grid::GridCell;
+use crate::bridge::MessageKind;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    // Lets the renderer tell new messages apart from ones it is already showing
+    pub id: u64,
+    pub kind: MessageKind,
+    pub content: Vec<GridCell>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessagesDisplay {
+    pub messages: Vec<Message>,
+    pub show_mode: Vec<GridCell>,
+    pub show_command: Vec<GridCell>,
+    pub ruler: Vec<GridCell>,
+    pub history: Option<Vec<Message>>,
+}
+
+// Tracks the externalized messages until the renderer is sent the next batch
+#[derive(Default)]
+pub struct Messages {
+    display: MessagesDisplay,
+    next_id: u64,
+    dirty: bool,
+}
+
+impl Messages {
+    pub fn new() -> Messages {
+        Messages::default()
+    }
+
+    fn create_message(&mut self, kind: MessageKind, content: Vec<GridCell>) -> Message {
+        self.next_id += 1;
+        Message {
+            id: self.next_id,
+            kind,
+            content,
+        }
+    }
+
+    pub fn show(&mut self, kind: MessageKind, content: Vec<GridCell>, replace_last: bool) {
+        let message = self.create_message(kind, content);
+        if replace_last {
+            self.display.messages.pop();
+        }
+        self.display.messages.push(message);
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.display.messages.clear();
+        self.display.history = None;
+        self.dirty = true;
+    }
+
+    pub fn set_show_mode(&mut self, content: Vec<GridCell>) {
+        self.display.show_mode = content;
+        self.dirty = true;
+    }
+
+    pub fn set_show_command(&mut self, content: Vec<GridCell>) {
+        self.display.show_command = content;
+        self.dirty = true;
+    }
+
+    pub fn set_ruler(&mut self, content: Vec<GridCell>) {
+        self.display.ruler = content;
+        self.dirty = true;
+    }
+
+    pub fn show_history(&mut self, entries: Vec<(MessageKind, Vec<GridCell>)>) {
+        let history = entries
+            .into_iter()
+            .map(|(kind, content)| self.create_message(kind, content))
+            .collect();
+        self.display.history = Some(history);
+        self.dirty = true;
+    }
+
+    pub fn reset(&mut self) {
+        self.display = MessagesDisplay::default();
+        self.dirty = true;
+    }
+
+    // Returns true once for every batch of changes
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    pub fn display(&self) -> MessagesDisplay {
+        self.display.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(text: &str) -> Vec<GridCell> {
+        text.chars()
+            .map(|character| (character.to_string(), None))
+            .collect()
+    }
+
+    #[test]
+    fn replace_last_swaps_the_newest_message() {
+        let mut messages = Messages::new();
+        messages.show(MessageKind::Echo, cells("first"), false);
+        messages.show(MessageKind::SearchCount, cells("[1/3]"), false);
+        messages.show(MessageKind::SearchCount, cells("[2/3]"), true);
+
+        let display = messages.display();
+        assert_eq!(display.messages.len(), 2);
+        assert_eq!(display.messages[1].content, cells("[2/3]"));
+        assert_ne!(display.messages[0].id, display.messages[1].id);
+    }
+
+    #[test]
+    fn clear_keeps_the_status_strip() {
+        let mut messages = Messages::new();
+        messages.set_show_mode(cells("-- INSERT --"));
+        messages.show(MessageKind::Error, cells("E492"), false);
+        messages.show_history(vec![(MessageKind::Echo, cells("old"))]);
+        assert!(messages.take_dirty());
+        assert!(!messages.take_dirty());
+
+        messages.clear();
+        let display = messages.display();
+        assert!(messages.take_dirty());
+        assert!(display.messages.is_empty());
+        assert!(display.history.is_none());
+        assert_eq!(display.show_mode, cells("-- INSERT --"));
+    }
+}
@@ -1,7 +1,11 @@
+mod command_line;
 mod cursor;
 mod draw_command_batcher;
 mod grid;
+mod messages;
+mod popup_menu;
 mod style;
+mod tab_line;
 mod window;
 
 use std::collections::HashMap;
@@ -10,14 +14,25 @@ use std::thread;
 
 use crossfire::mpsc::RxUnbounded;
 use log::{error, trace};
+use rmpv::Value;
 
-use crate::bridge::{EditorMode, GuiOption, RedrawEvent, WindowAnchor};
+use crate::bridge::{
+    EditorMode, GuiOption, NeovimCapabilities, PopupMenuItem, RedrawEvent, ScreencastRequest,
+    ScreenshotRequest, StartupFailure, TabInfo, UiExtensions, WindowAnchor,
+};
 use crate::channel_utils::*;
 use crate::redraw_scheduler::REDRAW_SCHEDULER;
+pub use command_line::CommandLineDisplay;
+use command_line::{styled_cells, CommandLine};
 pub use cursor::{Cursor, CursorMode, CursorShape};
 pub use draw_command_batcher::DrawCommandBatcher;
 pub use grid::CharacterGrid;
+use messages::Messages;
+pub use messages::{Message, MessagesDisplay};
+pub use popup_menu::{PopupMenuAnchor, PopupMenuDisplay};
 pub use style::{Colors, Style};
+use tab_line::tab_line_visible;
+pub use tab_line::TabLineDisplay;
 pub use window::*;
 
 #[derive(Clone, Debug)]
@@ -55,23 +70,57 @@ pub enum DrawCommand {
     },
     UpdateCursor(Cursor),
     FontChanged(String),
+    LineSpaceChanged(u64),
     DefaultStyleChanged(Style),
     ModeChanged(EditorMode),
+    CommandLine(Option<CommandLineDisplay>),
+    Messages(MessagesDisplay),
+    PopupMenu(Option<PopupMenuDisplay>),
+    TabLine(Option<TabLineDisplay>),
+    Disconnected(bool),
+    StartupFailure(Option<StartupFailure>),
+}
+
+#[derive(Debug)]
+pub enum EditorCommand {
+    NeovimRedrawEvent(RedrawEvent),
+    ConnectionLost,
+    ConnectionRestored,
+    StartupFailed(StartupFailure),
+    StartupRetried,
+    CapabilitiesChanged {
+        capabilities: NeovimCapabilities,
+        ui_extensions: UiExtensions,
+    },
 }
 
 #[derive(Debug)]
 pub enum WindowCommand {
     TitleChanged(String),
     SetMouseEnabled(bool),
+    FocusWindow,
+    Screenshot(ScreenshotRequest),
+    StartScreencast(ScreencastRequest),
+    StopScreencast,
 }
 
 pub struct Editor {
     pub windows: HashMap<u64, Window>,
     pub cursor: Cursor,
+    pub command_line: CommandLine,
+    pub messages: Messages,
     pub defined_styles: HashMap<u64, Arc<Style>>,
+    pub highlight_groups: HashMap<String, u64>,
+    pub popup_menu: Option<PopupMenuDisplay>,
+    pub pumblend: u64,
+    pub tabs: Vec<TabInfo>,
+    pub current_tab: Value,
+    pub show_tab_line: u64,
     pub mode_list: Vec<CursorMode>,
     pub draw_command_batcher: Arc<DrawCommandBatcher>,
     pub window_command_sender: LoggingSender<WindowCommand>,
+    pub capabilities: NeovimCapabilities,
+    pub ui_extensions: UiExtensions,
 }
 
 impl Editor {
@@ -82,13 +131,85 @@ impl Editor {
         Editor {
             windows: HashMap::new(),
             cursor: Cursor::new(),
+            command_line: CommandLine::new(),
+            messages: Messages::new(),
             defined_styles: HashMap::new(),
+            highlight_groups: HashMap::new(),
+            popup_menu: None,
+            pumblend: 0,
+            tabs: Vec::new(),
+            current_tab: Value::Nil,
+            // Neovim's default for 'showtabline'
+            show_tab_line: 1,
             mode_list: Vec::new(),
             draw_command_batcher: Arc::new(DrawCommandBatcher::new(batched_draw_command_sender)),
             window_command_sender,
+            capabilities: NeovimCapabilities::default(),
+            ui_extensions: UiExtensions::default(),
+        }
+    }
+
+    pub fn handle_editor_command(&mut self, command: EditorCommand) {
+        match command {
+            EditorCommand::NeovimRedrawEvent(event) => self.handle_redraw_event(event),
+            EditorCommand::ConnectionLost => {
+                // No flush is coming until we reconnect, so push the overlay out immediately and
+                // leave the last frame on screen underneath it.
+                self.draw_command_batcher
+                    .queue(DrawCommand::Disconnected(true))
+                    .ok();
+                self.draw_command_batcher.send_batch().ok();
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            EditorCommand::ConnectionRestored => {
+                // The changes are picked up by the first flush from the new session
+                self.reset_session();
+                self.draw_command_batcher
+                    .queue(DrawCommand::Disconnected(false))
+                    .ok();
+            }
+            EditorCommand::StartupFailed(failure) => {
+                // Neovim never got far enough to flush, so the error screen is sent right away
+                self.draw_command_batcher
+                    .queue(DrawCommand::StartupFailure(Some(failure)))
+                    .ok();
+                self.draw_command_batcher.send_batch().ok();
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            EditorCommand::StartupRetried => {
+                // The failed attempt may have sent a few grids before giving up
+                self.reset_session();
+                self.draw_command_batcher
+                    .queue(DrawCommand::StartupFailure(None))
+                    .ok();
+                self.draw_command_batcher.send_batch().ok();
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            EditorCommand::CapabilitiesChanged {
+                capabilities,
+                ui_extensions,
+            } => {
+                // A reconnect may land on a different server, so these are replaced every attach
+                self.capabilities = capabilities;
+                self.ui_extensions = ui_extensions;
+            }
         }
     }
 
+    // The server resends every grid on attach, and it may have been restarted with entirely
+    // different ones, so everything left over from the previous session is thrown away
+    fn reset_session(&mut self) {
+        let grids: Vec<u64> = self.windows.keys().copied().collect();
+        for grid in grids {
+            self.close_window(grid);
+        }
+        self.command_line.clear();
+        self.messages.reset();
+        self.hide_popup_menu();
+        self.tabs.clear();
+        self.send_tab_line();
+    }
+
     pub fn handle_redraw_event(&mut self, event: RedrawEvent) {
         match event {
             RedrawEvent::SetTitle { title } => {
@@ -126,6 +247,8 @@ impl Editor {
             }
             RedrawEvent::Flush => {
                 trace!("Image flushed");
+                self.send_command_line();
+                self.send_messages();
                 self.send_cursor_info();
                 self.draw_command_batcher.send_batch().ok();
                 REDRAW_SCHEDULER.queue_next_frame();
@@ -138,6 +261,9 @@ impl Editor {
             RedrawEvent::HighlightAttributesDefine { id, style } => {
                 self.defined_styles.insert(id, Arc::new(style));
             }
+            RedrawEvent::HighlightGroupSet { name, id } => {
+                self.highlight_groups.insert(name, id);
+            }
             RedrawEvent::CursorGoto {
                 grid,
                 column: left,
@@ -185,27 +311,34 @@ impl Editor {
             }
             RedrawEvent::WindowPosition {
                 grid,
+                window,
                 start_row,
                 start_column,
                 width,
                 height,
-            } => self.set_window_position(grid, start_column, start_row, width, height),
+            } => self.set_window_position(grid, window, start_column, start_row, width, height),
             RedrawEvent::WindowFloatPosition {
                 grid,
+                window,
                 anchor,
                 anchor_grid,
                 anchor_column: anchor_left,
                 anchor_row: anchor_top,
                 sort_order,
                 ..
-            } => self.set_window_float_position(
-                grid,
-                anchor_grid,
-                anchor,
-                anchor_left,
-                anchor_top,
-                sort_order,
-            ),
+            } => {
+                if let Some(floating_window) = self.windows.get_mut(&grid) {
+                    floating_window.window_handle = Some(window);
+                }
+                self.set_window_float_position(
+                    grid,
+                    anchor_grid,
+                    anchor,
+                    anchor_left,
+                    anchor_top,
+                    sort_order,
+                )
+            }
             RedrawEvent::WindowHide { grid } => {
                 let window = self.windows.get(&grid);
                 if let Some(window) = window {
@@ -222,6 +355,83 @@ impl Editor {
                 bottom_line,
                 ..
             } => self.send_updated_viewport(grid, top_line, bottom_line),
+            RedrawEvent::CommandLineShow {
+                content,
+                position,
+                first_character,
+                prompt,
+                indent,
+                level,
+            } => {
+                let content = styled_cells(&content, &self.defined_styles);
+                self.command_line
+                    .show(content, position, first_character, prompt, indent, level);
+            }
+            RedrawEvent::CommandLinePosition { position, level } => {
+                self.command_line.set_position(position, level)
+            }
+            RedrawEvent::CommandLineSpecialCharacter {
+                character,
+                shift,
+                level,
+            } => self
+                .command_line
+                .set_special_character(character, shift, level),
+            RedrawEvent::CommandLineHide => self.command_line.hide(),
+            RedrawEvent::CommandLineBlockShow { lines } => {
+                let lines = lines
+                    .iter()
+                    .map(|line| styled_cells(line, &self.defined_styles))
+                    .collect();
+                self.command_line.show_block(lines);
+            }
+            RedrawEvent::CommandLineBlockAppend { line } => {
+                let line = styled_cells(&line, &self.defined_styles);
+                self.command_line.append_block(line);
+            }
+            RedrawEvent::CommandLineBlockHide => self.command_line.hide_block(),
+            RedrawEvent::PopupMenuShow {
+                items,
+                selected,
+                row,
+                column,
+                grid,
+            } => self.show_popup_menu(items, selected, row, column, grid),
+            RedrawEvent::PopupMenuSelect { selected } => self.select_popup_menu_item(selected),
+            RedrawEvent::PopupMenuHide => self.hide_popup_menu(),
+            RedrawEvent::TablineUpdate { current_tab, tabs } => {
+                self.current_tab = current_tab;
+                self.tabs = tabs;
+                self.send_tab_line();
+            }
+            RedrawEvent::MessageShow {
+                kind,
+                content,
+                replace_last,
+            } => {
+                let content = styled_cells(&content, &self.defined_styles);
+                self.messages.show(kind, content, replace_last);
+            }
+            RedrawEvent::MessageClear => self.messages.clear(),
+            RedrawEvent::MessageShowMode { content } => {
+                let content = styled_cells(&content, &self.defined_styles);
+                self.messages.set_show_mode(content);
+            }
+            RedrawEvent::MessageShowCommand { content } => {
+                let content = styled_cells(&content, &self.defined_styles);
+                self.messages.set_show_command(content);
+            }
+            RedrawEvent::MessageRuler { content } => {
+                let content = styled_cells(&content, &self.defined_styles);
+                self.messages.set_ruler(content);
+            }
+            RedrawEvent::MessageHistoryShow { entries } => {
+                let entries = entries
+                    .iter()
+                    .map(|(kind, content)| (*kind, styled_cells(content, &self.defined_styles)))
+                    .collect();
+                self.messages.show_history(entries);
+            }
             _ => {}
         };
     }
@@ -242,6 +452,7 @@ impl Editor {
         } else {
             let window = Window::new(
                 grid,
+                None,
                 WindowType::Editor,
                 None,
                 (0.0, 0.0),
@@ -255,17 +466,20 @@ impl Editor {
     fn set_window_position(
         &mut self,
         grid: u64,
+        window_handle: u64,
         start_left: u64,
         start_top: u64,
         width: u64,
         height: u64,
     ) {
         if let Some(window) = self.windows.get_mut(&grid) {
+            window.window_handle = Some(window_handle);
             window.position(None, (width, height), (start_left as f64, start_top as f64));
             window.show();
         } else {
             let new_window = Window::new(
                 grid,
+                Some(window_handle),
                 WindowType::Editor,
                 None,
                 (start_left as f64, start_top as f64),
@@ -340,6 +554,7 @@ impl Editor {
         } else {
             let new_window = Window::new(
                 grid,
+                None,
                 WindowType::Message,
                 Some(anchor_info),
                 (0.0, grid_top as f64),
@@ -404,9 +619,104 @@ impl Editor {
         self.cursor.grid_position = (grid_left, grid_top);
     }
 
+    fn highlight_group_style(&self, name: &str) -> Option<Arc<Style>> {
+        let id = self.highlight_groups.get(name)?;
+        self.defined_styles.get(id).cloned()
+    }
+
+    fn show_popup_menu(
+        &mut self,
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        row: u64,
+        column: u64,
+        grid: Option<u64>,
+    ) {
+        let anchor = match grid {
+            Some(grid) => {
+                // Floating windows are placed relative to their anchor grid, so walk the anchors
+                // to find where the completed text is on screen
+                let (grid_left, grid_top) = self.get_window_top_left(grid).unwrap_or((0.0, 0.0));
+                PopupMenuAnchor::Grid {
+                    left: grid_left + column as f64,
+                    top: grid_top + row as f64,
+                }
+            }
+            None => PopupMenuAnchor::CommandLine { column },
+        };
+
+        let popup_menu = PopupMenuDisplay {
+            items: Arc::new(items),
+            selected,
+            anchor,
+            blend: self.pumblend,
+            style: self.highlight_group_style("Pmenu"),
+            selected_style: self.highlight_group_style("PmenuSel"),
+            thumb_style: self.highlight_group_style("PmenuThumb"),
+        };
+        self.popup_menu = Some(popup_menu.clone());
+        self.draw_command_batcher
+            .queue(DrawCommand::PopupMenu(Some(popup_menu)))
+            .ok();
+    }
+
+    fn select_popup_menu_item(&mut self, selected: Option<u64>) {
+        if let Some(popup_menu) = &mut self.popup_menu {
+            popup_menu.selected = selected;
+            self.draw_command_batcher
+                .queue(DrawCommand::PopupMenu(Some(popup_menu.clone())))
+                .ok();
+        }
+    }
+
+    fn hide_popup_menu(&mut self) {
+        if self.popup_menu.take().is_some() {
+            self.draw_command_batcher
+                .queue(DrawCommand::PopupMenu(None))
+                .ok();
+        }
+    }
+
+    fn send_tab_line(&mut self) {
+        let tab_line = if tab_line_visible(self.show_tab_line, self.tabs.len()) {
+            Some(TabLineDisplay {
+                tabs: self.tabs.clone(),
+                current_tab: self.current_tab.clone(),
+                style: self.highlight_group_style("TabLine"),
+                selected_style: self.highlight_group_style("TabLineSel"),
+                fill_style: self.highlight_group_style("TabLineFill"),
+            })
+        } else {
+            None
+        };
+        self.draw_command_batcher
+            .queue(DrawCommand::TabLine(tab_line))
+            .ok();
+    }
+
+    fn send_command_line(&mut self) {
+        if self.command_line.take_dirty() {
+            self.draw_command_batcher
+                .queue(DrawCommand::CommandLine(self.command_line.display()))
+                .ok();
+        }
+    }
+
+    fn send_messages(&mut self) {
+        if self.messages.take_dirty() {
+            self.draw_command_batcher
+                .queue(DrawCommand::Messages(self.messages.display()))
+                .ok();
+        }
+    }
+
     fn send_cursor_info(&mut self) {
         let (grid_left, grid_top) = self.cursor.grid_position;
-        if let Some(window) = self.windows.get(&self.cursor.parent_window_id) {
+        // The renderer moves the cursor into the command line box while it is open
+        if let Some(character) = self.command_line.cursor_character() {
+            self.cursor.character = character;
+            self.cursor.double_width = false;
+        } else if let Some(window) = self.windows.get(&self.cursor.parent_window_id) {
             let (character, double_width) = window.get_cursor_character(grid_left, grid_top);
             self.cursor.character = character;
             self.cursor.double_width = double_width;
@@ -421,13 +731,29 @@ impl Editor {
 
     fn set_option(&mut self, gui_option: GuiOption) {
         trace!("Option set {:?}", &gui_option);
-        if let GuiOption::GuiFont(guifont) = gui_option {
-            self.draw_command_batcher
-                .queue(DrawCommand::FontChanged(guifont))
-                .ok();
-            for window in self.windows.values() {
-                window.redraw();
+        match gui_option {
+            GuiOption::GuiFont(guifont) => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::FontChanged(guifont))
+                    .ok();
+                for window in self.windows.values() {
+                    window.redraw();
+                }
             }
+            GuiOption::LineSpace(linespace) => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::LineSpaceChanged(linespace))
+                    .ok();
+                for window in self.windows.values() {
+                    window.redraw();
+                }
+            }
+            GuiOption::Pumblend(pumblend) => self.pumblend = pumblend,
+            GuiOption::ShowTabLine(show_tab_line) => {
+                self.show_tab_line = show_tab_line;
+                self.send_tab_line();
+            }
+            _ => {}
         }
     }
 
@@ -441,15 +767,15 @@ impl Editor {
 }
 
 pub fn start_editor(
-    redraw_event_receiver: RxUnbounded<RedrawEvent>,
+    editor_command_receiver: RxUnbounded<EditorCommand>,
     batched_draw_command_sender: LoggingSender<Vec<DrawCommand>>,
     window_command_sender: LoggingSender<WindowCommand>,
 ) {
     thread::spawn(move || {
         let mut editor = Editor::new(batched_draw_command_sender, window_command_sender);
 
-        while let Ok(redraw_event) = redraw_event_receiver.recv_blocking() {
-            editor.handle_redraw_event(redraw_event);
+        while let Ok(editor_command) = editor_command_receiver.recv_blocking() {
+            editor.handle_editor_command(editor_command);
         }
     });
 }
@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use super::style::Style;
+use crate::bridge::PopupMenuItem;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PopupMenuAnchor {
+    // Position of the completed text in the global grid. The menu goes below that line, or above
+    // it if there isn't enough room.
+    Grid { left: f64, top: f64 },
+    // Column in the externalized command line
+    CommandLine { column: u64 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PopupMenuDisplay {
+    // Shared so that changing the selection doesn't copy every item
+    pub items: Arc<Vec<PopupMenuItem>>,
+    pub selected: Option<u64>,
+    pub anchor: PopupMenuAnchor,
+    pub blend: u64,
+    pub style: Option<Arc<Style>>,
+    pub selected_style: Option<Arc<Style>>,
+    pub thumb_style: Option<Arc<Style>>,
+}
@@ -51,6 +51,19 @@ impl Style {
         }
     }
 
+    // The background with blend applied, which like in the TUI goes from 0 for opaque to 100 for
+    // fully see through. Floating windows with winblend get it through their highlights.
+    pub fn blended_background(&self, default_colors: &Colors) -> Color4f {
+        let mut background = self.background(default_colors);
+        background.a *= 1.0 - self.blend.min(100) as f32 / 100.0;
+        background
+    }
+
+    // Window transparency only applies to cells drawn with the default background
+    pub fn uses_default_background(&self) -> bool {
+        !self.reverse && self.colors.background.is_none()
+    }
+
     pub fn special(&self, default_colors: &Colors) -> Color4f {
         self.colors
             .special
@@ -136,6 +149,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_blended_background() {
+        let mut style = Style::new(DEFAULT_COLORS);
+        style.colors.background = Some(Color4f::new(0.2, 0.2, 0.1, 1.0));
+
+        assert_eq!(style.blended_background(&DEFAULT_COLORS).a, 1.0);
+        style.blend = 25;
+        assert_eq!(style.blended_background(&DEFAULT_COLORS).a, 0.75);
+        style.blend = 200;
+        assert_eq!(style.blended_background(&DEFAULT_COLORS).a, 0.0);
+    }
+
+    #[test]
+    fn test_uses_default_background() {
+        let mut style = Style::new(COLORS);
+        assert!(!style.uses_default_background());
+
+        style.colors.background = None;
+        assert!(style.uses_default_background());
+        style.reverse = true;
+        assert!(!style.uses_default_background());
+    }
+
     #[test]
     fn test_special() {
         let mut style = Style::new(COLORS);
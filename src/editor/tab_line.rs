@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use rmpv::Value;
+
+use super::style::Style;
+use crate::bridge::TabInfo;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TabLineDisplay {
+    pub tabs: Vec<TabInfo>,
+    pub current_tab: Value,
+    pub style: Option<Arc<Style>>,
+    pub selected_style: Option<Arc<Style>>,
+    pub fill_style: Option<Arc<Style>>,
+}
+
+// Follows 'showtabline': 0 never shows the tab line, 1 only shows it when there is more than one
+// tab, and 2 always shows it
+pub fn tab_line_visible(show_tab_line: u64, tab_count: usize) -> bool {
+    match show_tab_line {
+        0 => false,
+        1 => tab_count > 1,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visibility_follows_showtabline() {
+        assert!(!tab_line_visible(0, 3));
+        assert!(!tab_line_visible(1, 1));
+        assert!(tab_line_visible(1, 2));
+        assert!(tab_line_visible(2, 1));
+    }
+}
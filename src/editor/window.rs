@@ -15,6 +15,7 @@ pub enum WindowDrawCommand {
         grid_position: (f64, f64),
         grid_size: (u64, u64),
         floating_order: Option<u64>,
+        window_handle: Option<u64>,
     },
     Cells {
         cells: Vec<String>,
@@ -48,6 +49,8 @@ pub enum WindowType {
 
 pub struct Window {
     grid_id: u64,
+    // The neovim window shown in the grid, only known with multigrid
+    pub window_handle: Option<u64>,
     grid: CharacterGrid,
     pub window_type: WindowType,
 
@@ -60,6 +63,7 @@ pub struct Window {
 impl Window {
     pub fn new(
         grid_id: u64,
+        window_handle: Option<u64>,
         window_type: WindowType,
         anchor_info: Option<AnchorInfo>,
         grid_position: (f64, f64),
@@ -68,6 +72,7 @@ impl Window {
     ) -> Window {
         let window = Window {
             grid_id,
+            window_handle,
             grid: CharacterGrid::new(grid_size),
             window_type,
             anchor_info,
@@ -92,6 +97,7 @@ impl Window {
             grid_position: self.grid_position,
             grid_size: (self.grid.width, self.grid.height),
             floating_order: self.anchor_info.clone().map(|anchor| anchor.sort_order),
+            window_handle: self.window_handle,
         });
     }
 
@@ -368,6 +374,7 @@ mod tests {
         let (batched_receiver, batched_sender) = build_test_channels();
         let mut window = Window::new(
             1,
+            None,
             WindowType::Editor,
             None,
             (0.0, 0.0),
@@ -34,12 +34,10 @@ use std::sync::{atomic::AtomicBool, mpsc::channel, Arc};
 
 use crossfire::mpsc::unbounded_future;
 
-use bridge::start_bridge;
-#[cfg(not(test))]
+use bridge::{forward_to_running_instance, start_bridge, start_replay, SingleInstanceSettings};
 use cmd_line::CmdLineSettings;
 use editor::start_editor;
-use renderer::{cursor_renderer::CursorSettings, RendererSettings};
-#[cfg(not(test))]
+use renderer::{cursor_renderer::CursorSettings, FontSettings, MessageSettings, RendererSettings};
 use settings::SETTINGS;
 use window::{create_window, WindowSettings};
 
@@ -73,7 +71,8 @@ fn main() {
     //   Redraw events are direct events from the neovim process meant to specify how the editor
     //   should be drawn to the screen. They also include other things such as whether the mouse is
     //   enabled. The bridge takes these events, filters out some of them meant only for
-    //   filtering, and forwards them to the editor.
+    //   filtering, and forwards them to the editor. Connection changes of remote sessions are
+    //   forwarded the same way so the editor can reset itself on reconnect.
     //
     // EDITOR:
     //   The editor is responsible for processing and transforming redraw events into something
@@ -114,6 +113,10 @@ fn main() {
     //   another frame next frame, or if it can safely skip drawing to save battery and cpu power.
     //   Multiple other parts of the app "queue_next_frame" function to ensure animations continue
     //   properly or updates to the graphics are pushed to the screen.
+    //
+    // WINDOW INFO:
+    //   A snapshot of the window size, cell size and monitors kept up to date by the window, so
+    //   the bridge can answer requests from neovim without waiting on the window thread.
 
     //Will exit if -h or -v
     if let Err(err) = cmd_line::handle_command_line_arguments() {
@@ -124,11 +127,18 @@ fn main() {
     #[cfg(not(test))]
     init_logger();
 
+    // Checked before disowning on macos so that the forwarding instance exits from the terminal
+    if SETTINGS.get::<CmdLineSettings>().single_instance && forward_to_running_instance() {
+        return;
+    }
+
     #[cfg(target_os = "macos")]
     {
         // incase of app bundle, we can just pass --disowned option straight away to bypass this check
+        // --nofork keeps the process around so that whoever launched it can wait for the editor
         #[cfg(not(debug_assertions))]
-        if !SETTINGS.get::<CmdLineSettings>().disowned {
+        if !SETTINGS.get::<CmdLineSettings>().disowned && !SETTINGS.get::<CmdLineSettings>().no_fork
+        {
             if let Ok(curr_exe) = std::env::current_exe() {
                 assert!(std::process::Command::new(curr_exe)
                     .args(std::env::args().skip(1))
@@ -163,12 +173,15 @@ fn main() {
     WindowSettings::register();
     RendererSettings::register();
     CursorSettings::register();
+    FontSettings::register();
+    MessageSettings::register();
+    SingleInstanceSettings::register();
 
     let running = Arc::new(AtomicBool::new(true));
 
-    let (redraw_event_sender, redraw_event_receiver) = unbounded_future();
-    let logging_redraw_event_sender =
-        LoggingTx::attach(redraw_event_sender, "redraw_event".to_owned());
+    let (editor_command_sender, editor_command_receiver) = unbounded_future();
+    let logging_editor_command_sender =
+        LoggingTx::attach(editor_command_sender, "editor_command".to_owned());
 
     let (batched_draw_command_sender, batched_draw_command_receiver) = channel();
     let logging_batched_draw_command_sender = LoggingSender::attach(
@@ -184,23 +197,39 @@ fn main() {
         LoggingSender::attach(window_command_sender, "window_command".to_owned());
 
     // We need to keep the bridge reference around to prevent the tokio runtime from getting freed
-    let _bridge = start_bridge(
-        logging_ui_command_sender.clone(),
-        ui_command_receiver,
-        logging_redraw_event_sender,
-        running.clone(),
-    );
+    let _bridge = if let Some(replay_path) = SETTINGS.get::<CmdLineSettings>().replay {
+        start_replay(
+            replay_path,
+            logging_editor_command_sender,
+            ui_command_receiver,
+            running.clone(),
+        );
+        None
+    } else {
+        Some(start_bridge(
+            logging_ui_command_sender.clone(),
+            ui_command_receiver,
+            logging_editor_command_sender,
+            logging_window_command_sender.clone(),
+            running.clone(),
+        ))
+    };
     start_editor(
-        redraw_event_receiver,
+        editor_command_receiver,
         logging_batched_draw_command_sender,
         logging_window_command_sender,
     );
-    create_window(
+    // The window logs why it couldn't be created
+    if create_window(
         batched_draw_command_receiver,
         window_command_receiver,
         logging_ui_command_sender,
         running,
-    );
+    )
+    .is_err()
+    {
+        std::process::exit(1);
+    }
 }
 
 #[cfg(not(test))]
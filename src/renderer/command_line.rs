@@ -0,0 +1,85 @@
+use skia_safe::{Canvas, Paint, Point, RRect, Rect};
+
+use super::{wrap_cells, Renderer};
+
+const MIN_COMMAND_LINE_WIDTH: u64 = 40;
+// Fraction of the editor height above the command line box
+const COMMAND_LINE_VERTICAL_POSITION: f32 = 0.2;
+const BORDER_OPACITY: f32 = 0.4;
+
+impl Renderer {
+    // Draws the externalized command line as a box floating over the upper part of the editor and
+    // returns where the cursor should be drawn inside of it
+    pub(super) fn draw_command_line(&mut self, root_canvas: &mut Canvas) -> Option<Point> {
+        self.command_line_region = None;
+        let command_line = self.command_line.clone()?;
+
+        let (grid_width, grid_height) = self
+            .rendered_windows
+            .get(&1)
+            .map(|root_window| (root_window.grid_width, root_window.grid_height))
+            .unwrap_or((MIN_COMMAND_LINE_WIDTH, 1));
+
+        let max_width = grid_width.saturating_sub(4).max(1);
+        let longest_line = command_line
+            .block_lines
+            .iter()
+            .map(|line| line.len() as u64)
+            .chain(std::iter::once(command_line.line.len() as u64 + 1))
+            .max()
+            .unwrap_or(0);
+        let width = longest_line
+            .max(MIN_COMMAND_LINE_WIDTH.min(max_width))
+            .min(max_width);
+
+        let mut rows = Vec::new();
+        for line in command_line.block_lines.iter() {
+            rows.extend(wrap_cells(line, width as usize));
+        }
+        let line_start_row = rows.len() as u64;
+        rows.extend(wrap_cells(&command_line.line, width as usize));
+
+        let cursor_row = line_start_row + command_line.cursor_column / width;
+        let cursor_column = command_line.cursor_column % width;
+        let height = (rows.len() as u64).max(cursor_row + 1);
+
+        let font_width = self.font_width as f32;
+        let font_height = self.font_height as f32;
+        let padding = font_width;
+        let box_width = width as f32 * font_width + padding * 2.0;
+        let box_height = height as f32 * font_height + padding * 2.0;
+        let left = ((grid_width as f32 * font_width - box_width) / 2.0)
+            .max(0.0)
+            .floor();
+        let top = (grid_height as f32 * font_height * COMMAND_LINE_VERTICAL_POSITION).floor();
+
+        let box_rect = RRect::new_rect_xy(
+            Rect::from_xywh(left, top, box_width, box_height),
+            padding / 2.0,
+            padding / 2.0,
+        );
+        let mut paint = Paint::new(self.default_style.colors.background.unwrap(), None);
+        paint.set_anti_alias(true);
+        root_canvas.draw_rrect(&box_rect, &paint);
+
+        let mut border_color = self.default_style.colors.foreground.unwrap();
+        border_color.a = BORDER_OPACITY;
+        paint.set_color(border_color.to_color());
+        paint.set_stroke(true);
+        paint.set_stroke_width(1.0);
+        root_canvas.draw_rrect(&box_rect, &paint);
+        self.command_line_region = Some(*box_rect.rect());
+
+        root_canvas.save();
+        root_canvas.translate((left + padding, top + padding));
+        for (row_index, row) in rows.iter().enumerate() {
+            self.draw_styled_cells(root_canvas, row, (0, row_index as u64));
+        }
+        root_canvas.restore();
+
+        Some(Point::new(
+            left + padding + cursor_column as f32 * font_width,
+            top + padding + cursor_row as f32 * font_height,
+        ))
+    }
+}
@@ -256,6 +256,10 @@ impl CursorRenderer {
         }
     }
 
+    pub fn set_destination(&mut self, destination: Point) {
+        self.destination = destination;
+    }
+
     pub fn draw(
         &mut self,
         default_colors: &Colors,
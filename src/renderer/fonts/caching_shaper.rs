@@ -19,12 +19,57 @@ struct ShapeKey {
     pub italic: bool,
 }
 
+// Where glyphs go in a cell, worked out from the font metrics and the spacing settings
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CellMetrics {
+    width: u64,
+    height: u64,
+    // Letter spacing is split on both sides of the glyphs
+    x_offset: f32,
+    // From the top of the cell to the baseline
+    y_adjustment: u64,
+    underline_position: u64,
+}
+
+impl CellMetrics {
+    fn new(
+        metrics: &Metrics,
+        scale_factor: f32,
+        linespace: u64,
+        letter_spacing: f32,
+        line_height: f32,
+    ) -> CellMetrics {
+        let glyph_height = (metrics.ascent + metrics.descent + metrics.leading).ceil() as u64;
+        let linespace = (linespace as f32 * scale_factor).round() as u64;
+        let height = ((glyph_height as f32 * line_height).ceil() as u64 + linespace).max(1);
+        let width = ((metrics.average_width * letter_spacing) as u64).max(1);
+
+        // The glyphs are centered in the cell
+        let padding = height.saturating_sub(glyph_height);
+        let (top_padding, bottom_padding) = (padding / 2, padding - padding / 2);
+
+        CellMetrics {
+            width,
+            height,
+            x_offset: (width as f32 - metrics.average_width).max(0.0) / 2.0,
+            y_adjustment: (metrics.ascent + metrics.leading).ceil() as u64 + top_padding,
+            underline_position: metrics.underline_offset as u64 + bottom_padding,
+        }
+    }
+}
+
 pub struct CachingShaper {
     options: FontOptions,
     font_loader: FontLoader,
     blob_cache: LruCache<ShapeKey, Vec<TextBlob>>,
     shape_context: ShapeContext,
     scale_factor: f32,
+    linespace: u64,
+    letter_spacing: f32,
+    line_height: f32,
+    font_features: FontFeatures,
+    // Worked out again whenever the font or the spacing changes
+    cell_metrics: Option<CellMetrics>,
 }
 
 impl CachingShaper {
@@ -37,19 +82,42 @@ impl CachingShaper {
             blob_cache: LruCache::new(10000),
             shape_context: ShapeContext::new(),
             scale_factor,
+            linespace: 0,
+            letter_spacing: 1.0,
+            line_height: 1.0,
+            font_features: FontFeatures::default(),
+            cell_metrics: None,
         }
     }
 
-    fn current_font_pair(&mut self) -> Arc<FontPair> {
-        let default_key = FontKey::default();
-        let font_key = FontKey::from(&self.options);
+    // The fonts of guifont in the order they were given, followed by the bundled font
+    fn font_list_keys(&self, bold: bool, italic: bool) -> Vec<FontKey> {
+        let bold = self.options.bold || bold;
+        let italic = self.options.italic || italic;
+
+        self.options
+            .font_list
+            .iter()
+            .map(FontSelection::from)
+            .chain(std::iter::once(FontSelection::Default))
+            .map(|font_selection| FontKey {
+                bold,
+                italic,
+                font_selection,
+            })
+            .collect()
+    }
 
-        if let Some(font_pair) = self.font_loader.get_or_load(&font_key) {
-            return font_pair;
+    // The cell size comes from the first font of guifont which is installed
+    fn current_font_pair(&mut self) -> Arc<FontPair> {
+        for font_key in self.font_list_keys(false, false) {
+            if let Some(font_pair) = self.font_loader.get_or_load(&font_key) {
+                return font_pair;
+            }
         }
 
         self.font_loader
-            .get_or_load(&default_key)
+            .get_or_load(&FontKey::default())
             .expect("Could not load font")
     }
 
@@ -70,41 +138,101 @@ impl CachingShaper {
         self.reset_font_loader();
     }
 
+    // Extra pixels between the lines, as set with 'linespace'
+    pub fn update_linespace(&mut self, linespace: u64) {
+        trace!("linespace changed: {}", linespace);
+        self.linespace = linespace;
+        self.cell_metrics = None;
+    }
+
+    // Returns whether the multipliers changed, which changes the cell size
+    pub fn update_spacing(&mut self, letter_spacing: f32, line_height: f32) -> bool {
+        let letter_spacing = letter_spacing.max(0.1);
+        let line_height = line_height.max(0.1);
+        if (self.letter_spacing - letter_spacing).abs() < f32::EPSILON
+            && (self.line_height - line_height).abs() < f32::EPSILON
+        {
+            return false;
+        }
+
+        trace!(
+            "spacing changed: letter spacing {:.2}, line height {:.2}",
+            letter_spacing,
+            line_height
+        );
+        self.letter_spacing = letter_spacing;
+        self.line_height = line_height;
+        self.cell_metrics = None;
+        // The glyphs are positioned by the cell width
+        self.blob_cache.clear();
+        true
+    }
+
+    pub fn update_features(&mut self, font_features: FontFeatures) {
+        if self.font_features != font_features {
+            trace!("Font features changed: {:?}", font_features);
+            self.font_features = font_features;
+            self.blob_cache.clear();
+        }
+    }
+
+    // The features from guifont, followed by the ones g:neovide_font_features sets for the family
+    fn features(&self, font_pair: &FontPair) -> Vec<FontFeature> {
+        let family = font_pair
+            .skia_font
+            .typeface()
+            .map(|typeface| typeface.family_name())
+            .unwrap_or_default();
+
+        let mut features = self.options.features.clone();
+        features.extend(self.font_features.for_family(&family));
+        features
+    }
+
     fn reset_font_loader(&mut self) {
         let font_size = self.options.size * self.scale_factor;
         trace!("Using font_size: {:.2}px", font_size);
 
         self.font_loader = FontLoader::new(font_size);
+        self.cell_metrics = None;
         self.blob_cache.clear();
     }
 
-    fn metrics(&mut self) -> Metrics {
+    fn cell_metrics(&mut self) -> CellMetrics {
+        if let Some(cell_metrics) = self.cell_metrics {
+            return cell_metrics;
+        }
+
         let font_pair = self.current_font_pair();
         let size = self.current_size();
-        let shaper = self
+        let metrics = self
             .shape_context
             .builder(font_pair.swash_font.as_ref())
             .size(size)
-            .build();
-
-        shaper.metrics()
+            .build()
+            .metrics();
+        let cell_metrics = CellMetrics::new(
+            &metrics,
+            self.scale_factor,
+            self.linespace,
+            self.letter_spacing,
+            self.line_height,
+        );
+        self.cell_metrics = Some(cell_metrics);
+        cell_metrics
     }
 
     pub fn font_base_dimensions(&mut self) -> (u64, u64) {
-        let metrics = self.metrics();
-        let font_height = (metrics.ascent + metrics.descent + metrics.leading).ceil() as u64;
-        let font_width = metrics.average_width as u64;
-
-        (font_width, font_height)
+        let cell_metrics = self.cell_metrics();
+        (cell_metrics.width, cell_metrics.height)
     }
 
     pub fn underline_position(&mut self) -> u64 {
-        self.metrics().underline_offset as u64
+        self.cell_metrics().underline_position
     }
 
     pub fn y_adjustment(&mut self) -> u64 {
-        let metrics = self.metrics();
-        (metrics.ascent + metrics.leading).ceil() as u64
+        self.cell_metrics().y_adjustment
     }
 
     fn build_clusters(
@@ -138,30 +266,17 @@ impl CachingShaper {
                 .flatten(),
         );
 
+        // Every font of guifont is tried in order, then the bundled font, before asking the system
+        let font_list_keys = self.font_list_keys(bold, italic);
+
         let mut results = Vec::new();
         'cluster: while parser.next(&mut cluster) {
-            // TODO: Don't redo this work for every cluster. Save it some how
-            // Create font fallback list
-            let mut font_fallback_keys = Vec::new();
+            let mut font_fallback_keys = font_list_keys.clone();
 
-            // Add parsed fonts from guifont
-            font_fallback_keys.extend(self.options.font_list.iter().map(|font_name| FontKey {
-                italic: self.options.italic || italic,
-                bold: self.options.bold || bold,
-                font_selection: font_name.into(),
-            }));
-
-            // Add default font
+            // Add skia fallback, which the font loader remembers for each character
             font_fallback_keys.push(FontKey {
                 italic: self.options.italic || italic,
                 bold: self.options.bold || bold,
-                font_selection: FontSelection::Default,
-            });
-
-            // Add skia fallback
-            font_fallback_keys.push(FontKey {
-                italic,
-                bold,
                 font_selection: cluster.chars()[0].ch.into(),
             });
 
@@ -223,7 +338,11 @@ impl CachingShaper {
 
     pub fn shape(&mut self, cells: &[String], bold: bool, italic: bool) -> Vec<TextBlob> {
         let current_size = self.current_size();
-        let (glyph_width, _glyph_height) = self.font_base_dimensions();
+        let CellMetrics {
+            width: glyph_width,
+            x_offset,
+            ..
+        } = self.cell_metrics();
 
         let mut resulting_blobs = Vec::new();
 
@@ -231,10 +350,12 @@ impl CachingShaper {
         trace!("Shaping text: {}", text);
 
         for (cluster_group, font_pair) in self.build_clusters(&text, bold, italic) {
+            let features = self.features(&font_pair);
             let mut shaper = self
                 .shape_context
                 .builder(font_pair.swash_font.as_ref())
                 .size(current_size)
+                .features(features.iter().map(|(tag, value)| (tag.as_str(), *value)))
                 .build();
 
             let charmap = font_pair.swash_font.as_ref().charmap();
@@ -247,7 +368,7 @@ impl CachingShaper {
 
             shaper.shape_with(|glyph_cluster| {
                 for glyph in glyph_cluster.glyphs {
-                    let position = ((glyph.data as u64 * glyph_width) as f32, glyph.y);
+                    let position = ((glyph.data as u64 * glyph_width) as f32 + x_offset, glyph.y);
                     glyph_data.push((glyph.id, position));
                 }
             });
@@ -282,3 +403,54 @@ impl CachingShaper {
         self.blob_cache.get(&key).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> Metrics {
+        Metrics {
+            ascent: 11.6,
+            descent: 3.2,
+            leading: 0.0,
+            average_width: 8.4,
+            underline_offset: 1.5,
+            ..Metrics::default()
+        }
+    }
+
+    #[test]
+    fn cells_fit_the_glyphs() {
+        let cell_metrics = CellMetrics::new(&metrics(), 1.0, 0, 1.0, 1.0);
+        assert_eq!((cell_metrics.width, cell_metrics.height), (8, 15));
+        assert_eq!(cell_metrics.y_adjustment, 12);
+        assert_eq!(cell_metrics.underline_position, 1);
+    }
+
+    #[test]
+    fn linespace_is_scaled() {
+        let cell_metrics = CellMetrics::new(&metrics(), 1.5, 3, 1.0, 1.0);
+        // 3 pixels of linespace become 4.5, rounded to 5, which are split around the glyphs
+        assert_eq!(cell_metrics.height, 20);
+        assert_eq!(cell_metrics.y_adjustment, 14);
+        assert_eq!(cell_metrics.underline_position, 4);
+    }
+
+    #[test]
+    fn line_height_rounds_up() {
+        let cell_metrics = CellMetrics::new(&metrics(), 1.0, 0, 1.0, 1.1);
+        assert_eq!(cell_metrics.height, 17);
+        assert_eq!(cell_metrics.y_adjustment, 13);
+    }
+
+    #[test]
+    fn letter_spacing_keeps_cells_at_least_a_pixel_wide() {
+        let wide = CellMetrics::new(&metrics(), 1.0, 0, 1.5, 1.0);
+        assert_eq!(wide.width, 12);
+        assert!((wide.x_offset - 1.8).abs() < 0.001);
+
+        let narrow = CellMetrics::new(&metrics(), 1.0, 0, 0.1, 1.0);
+        assert_eq!(narrow.width, 1);
+        assert!(narrow.x_offset.abs() < 0.001);
+    }
+}
@@ -1,9 +1,9 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use lru::LruCache;
 use skia_safe::{font::Edging, Data, Font, FontHinting, FontMgr, FontStyle, Typeface};
 
-use super::font_options::FontOptions;
 use super::swash_font::SwashFont;
 
 #[derive(RustEmbed)]
@@ -43,6 +43,14 @@ impl PartialEq for FontPair {
 pub struct FontLoader {
     font_mgr: FontMgr,
     cache: LruCache<FontKey, Arc<FontPair>>,
+    // Fonts which could not be loaded, so that missing guifont entries aren't searched for again
+    // for every cluster
+    failed: HashSet<FontKey>,
+    // The font the system picked for each character key, or None when no font has it
+    character_fallbacks: HashMap<FontKey, Option<Arc<FontPair>>>,
+    // The fonts picked for characters by typeface id, so that all the characters of one fallback
+    // font share it
+    fallback_fonts: HashMap<u32, Arc<FontPair>>,
     font_size: f32,
 }
 
@@ -65,16 +73,6 @@ impl Default for FontKey {
     }
 }
 
-impl From<&FontOptions> for FontKey {
-    fn from(options: &FontOptions) -> FontKey {
-        FontKey {
-            italic: options.italic,
-            bold: options.bold,
-            font_selection: options.primary_font(),
-        }
-    }
-}
-
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 pub enum FontSelection {
     Name(String),
@@ -113,33 +111,56 @@ impl FontLoader {
     pub fn new(font_size: f32) -> FontLoader {
         FontLoader {
             font_mgr: FontMgr::new(),
-            cache: LruCache::new(10),
+            cache: LruCache::new(20),
+            failed: HashSet::new(),
+            character_fallbacks: HashMap::new(),
+            fallback_fonts: HashMap::new(),
             font_size,
         }
     }
 
-    fn load(&mut self, font_key: FontKey) -> Option<FontPair> {
-        let font_style = match (font_key.bold, font_key.italic) {
+    fn font_style(font_key: &FontKey) -> FontStyle {
+        match (font_key.bold, font_key.italic) {
             (true, true) => FontStyle::bold_italic(),
             (false, true) => FontStyle::italic(),
             (true, false) => FontStyle::bold(),
             (false, false) => FontStyle::normal(),
-        };
+        }
+    }
+
+    // Asks the system which font has the character. The typeface it matched is used as is, since
+    // looking its family up again by name can pick a different face that lacks the character.
+    fn match_character(&mut self, font_key: &FontKey, character: char) -> Option<Arc<FontPair>> {
+        let typeface = self.font_mgr.match_family_style_character(
+            "",
+            FontLoader::font_style(font_key),
+            &[],
+            character as i32,
+        )?;
+
+        let typeface_id = typeface.unique_id();
+        if let Some(font_pair) = self.fallback_fonts.get(&typeface_id) {
+            return Some(font_pair.clone());
+        }
+
+        let font_pair = Arc::new(FontPair::new(Font::from_typeface(
+            typeface,
+            self.font_size,
+        ))?);
+        self.fallback_fonts.insert(typeface_id, font_pair.clone());
+        Some(font_pair)
+    }
+
+    fn load(&mut self, font_key: FontKey) -> Option<FontPair> {
+        let font_style = FontLoader::font_style(&font_key);
 
         match font_key.font_selection {
             FontSelection::Name(name) => {
                 let typeface = self.font_mgr.match_family_style(name, font_style)?;
                 FontPair::new(Font::from_typeface(typeface, self.font_size))
             }
-            FontSelection::Character(character) => {
-                let typeface = self.font_mgr.match_family_style_character(
-                    "",
-                    font_style,
-                    &[],
-                    character as i32,
-                )?;
-                FontPair::new(Font::from_typeface(typeface, self.font_size))
-            }
+            // Matched to a typeface by get_or_load
+            FontSelection::Character(_) => None,
             FontSelection::Default => {
                 let default_font_data = Asset::get(DEFAULT_FONT).unwrap();
                 let data = Data::new_copy(&default_font_data);
@@ -156,11 +177,31 @@ impl FontLoader {
     }
 
     pub fn get_or_load(&mut self, font_key: &FontKey) -> Option<Arc<FontPair>> {
+        if let FontSelection::Character(character) = font_key.font_selection {
+            if let Some(font_pair) = self.character_fallbacks.get(font_key) {
+                return font_pair.clone();
+            }
+
+            let font_pair = self.match_character(font_key, character);
+            self.character_fallbacks
+                .insert(font_key.clone(), font_pair.clone());
+            return font_pair;
+        }
+
         if let Some(cached) = self.cache.get(font_key) {
             return Some(cached.clone());
         }
+        if self.failed.contains(font_key) {
+            return None;
+        }
 
-        let loaded_font = self.load(font_key.clone())?;
+        let loaded_font = match self.load(font_key.clone()) {
+            Some(loaded_font) => loaded_font,
+            None => {
+                self.failed.insert(font_key.clone());
+                return None;
+            }
+        };
 
         let font_arc = Arc::new(loaded_font);
 
@@ -1,13 +1,129 @@
-use super::font_loader::FontSelection;
+use std::collections::HashMap;
+
+use log::error;
+
+use crate::settings::*;
 
 const DEFAULT_FONT_SIZE: f32 = 14.0;
 
+// letter_spacing and line_height multiply the size of the cells, on top of what the font asks for
+#[derive(SettingGroup)]
+#[setting_prefix = "font"]
+#[derive(Clone, PartialEq)]
+pub struct FontSettings {
+    pub letter_spacing: f32,
+    pub line_height: f32,
+    pub features: FontFeatures,
+}
+
+impl Default for FontSettings {
+    fn default() -> Self {
+        Self {
+            letter_spacing: 1.0,
+            line_height: 1.0,
+            features: FontFeatures::default(),
+        }
+    }
+}
+
+// An OpenType feature tag and its value, where 0 turns the feature off
+pub type FontFeature = (String, u16);
+
+// Features are written as +ss01 to turn them on, -calt to turn them off and cv01=2 to pick an
+// alternate. OpenType tags are always four characters long. Bare tags aren't accepted, since other
+// guis' options such as cANSI or w100 would read as one.
+pub fn parse_feature(feature: &str) -> Option<FontFeature> {
+    let (tag, value) = if let Some(tag) = feature.strip_prefix('+') {
+        (tag, 1)
+    } else if let Some(tag) = feature.strip_prefix('-') {
+        (tag, 0)
+    } else if let Some(separator) = feature.find('=') {
+        (
+            &feature[..separator],
+            feature[separator + 1..].parse().ok()?,
+        )
+    } else {
+        return None;
+    };
+
+    let valid_tag = tag.len() == 4 && tag.chars().all(|c| c.is_ascii_alphanumeric());
+    if valid_tag {
+        Some((tag.to_owned(), value))
+    } else {
+        None
+    }
+}
+
+// The features to shape each font family with, set through g:neovide_font_features as a dictionary
+// of lists such as {'Fira Code': ['+ss01', '-calt']}
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FontFeatures(HashMap<String, Vec<String>>);
+
+impl FontFeatures {
+    pub fn for_family(&self, family: &str) -> Vec<FontFeature> {
+        self.0
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(family))
+            .flat_map(|(_, features)| features.iter())
+            .filter_map(|feature| parse_feature(feature))
+            .collect()
+    }
+}
+
+impl FromValue for FontFeatures {
+    fn from_value(&mut self, value: Value) {
+        let entries = match value {
+            Value::Map(entries) => entries,
+            value => {
+                error!(
+                    "Setting expected a dictionary of font features, but received {:?}",
+                    value
+                );
+                return;
+            }
+        };
+
+        let mut families = HashMap::new();
+        for (family, features) in entries {
+            let features = features.as_array().and_then(|features| {
+                features
+                    .iter()
+                    .map(|feature| feature.as_str().map(str::to_owned))
+                    .collect::<Option<Vec<String>>>()
+            });
+            match (family.as_str(), features) {
+                (Some(family), Some(features)) => {
+                    families.insert(family.to_owned(), features);
+                }
+                (family, _) => error!("Expected a list of font features for {:?}", family),
+            }
+        }
+        *self = FontFeatures(families);
+    }
+}
+
+impl From<FontFeatures> for Value {
+    fn from(features: FontFeatures) -> Self {
+        Value::Map(
+            features
+                .0
+                .into_iter()
+                .map(|(family, features)| {
+                    let features = features.into_iter().map(Value::from).collect();
+                    (Value::from(family), Value::Array(features))
+                })
+                .collect(),
+        )
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct FontOptions {
     pub font_list: Vec<String>,
     pub size: f32,
     pub bold: bool,
     pub italic: bool,
+    pub features: Vec<FontFeature>,
 }
 
 impl FontOptions {
@@ -16,8 +132,10 @@ impl FontOptions {
         let mut size = DEFAULT_FONT_SIZE;
         let mut bold = false;
         let mut italic = false;
+        let mut features = Vec::new();
 
-        let mut parts = guifont_setting.split(':').filter(|part| !part.is_empty());
+        // The font list may be left empty to only set options, as in ":h12"
+        let mut parts = guifont_setting.split(':');
 
         if let Some(parts) = parts.next() {
             let parsed_font_list: Vec<String> = parts
@@ -31,7 +149,7 @@ impl FontOptions {
             }
         }
 
-        for part in parts {
+        for part in parts.filter(|part| !part.is_empty()) {
             if part.starts_with('h') && part.len() > 1 {
                 if let Ok(parsed_size) = part[1..].parse::<f32>() {
                     size = parsed_size
@@ -40,6 +158,10 @@ impl FontOptions {
                 bold = true;
             } else if part == "i" {
                 italic = true;
+            } else {
+                // Features given in guifont, such as :+ss01:cv01=2, apply to all of its fonts.
+                // Options of other guis, like :cANSI, are ignored.
+                features.extend(parse_feature(part));
             }
         }
 
@@ -47,16 +169,10 @@ impl FontOptions {
             font_list,
             bold,
             italic,
+            features,
             size: points_to_pixels(size),
         }
     }
-
-    pub fn primary_font(&self) -> FontSelection {
-        self.font_list
-            .first()
-            .map(FontSelection::from)
-            .unwrap_or(FontSelection::Default)
-    }
 }
 
 impl Default for FontOptions {
@@ -65,6 +181,7 @@ impl Default for FontOptions {
             font_list: Vec::new(),
             bold: false,
             italic: false,
+            features: Vec::new(),
             size: points_to_pixels(DEFAULT_FONT_SIZE),
         }
     }
@@ -76,6 +193,7 @@ impl PartialEq for FontOptions {
             && (self.size - other.size).abs() < std::f32::EPSILON
             && self.bold == other.bold
             && self.italic == other.italic
+            && self.features == other.features
     }
 }
 
@@ -100,3 +218,53 @@ fn points_to_pixels(value: f32) -> f32 {
 
     value * pixels_per_point
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_are_parsed() {
+        assert_eq!(parse_feature("+ss01"), Some(("ss01".to_owned(), 1)));
+        assert_eq!(parse_feature("-calt"), Some(("calt".to_owned(), 0)));
+        assert_eq!(parse_feature("cv01=2"), Some(("cv01".to_owned(), 2)));
+        assert_eq!(parse_feature("+zero"), Some(("zero".to_owned(), 1)));
+        assert_eq!(parse_feature("zero"), None);
+        assert_eq!(parse_feature("-ligatures"), None);
+        assert_eq!(parse_feature("u"), None);
+        assert_eq!(parse_feature("cv01=alternate"), None);
+    }
+
+    #[test]
+    fn guifont_suffixes_set_features() {
+        let options = FontOptions::parse(
+            "Fira Code,Noto Sans CJK JP:h12:+ss01:-calt:+zero:cv01=2:b:cANSI:w100",
+        );
+        assert_eq!(options.font_list, vec!["Fira Code", "Noto Sans CJK JP"]);
+        assert!(options.bold);
+        assert_eq!(
+            options.features,
+            vec![
+                ("ss01".to_owned(), 1),
+                ("calt".to_owned(), 0),
+                ("zero".to_owned(), 1),
+                ("cv01".to_owned(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn features_are_looked_up_by_family() {
+        let mut features = FontFeatures::default();
+        features.from_value(Value::Map(vec![(
+            Value::from("Fira Code"),
+            Value::from(vec![Value::from("+ss01"), Value::from("-calt")]),
+        )]));
+
+        assert_eq!(
+            features.for_family("fira code"),
+            vec![("ss01".to_owned(), 1), ("calt".to_owned(), 0)]
+        );
+        assert!(features.for_family("Iosevka").is_empty());
+    }
+}
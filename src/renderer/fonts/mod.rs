@@ -2,3 +2,5 @@ pub mod caching_shaper;
 mod font_loader;
 mod font_options;
 mod swash_font;
+
+pub use font_options::FontSettings;
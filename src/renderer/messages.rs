@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use skia_safe::{Canvas, Color, Paint, Point, RRect, Rect};
+
+use super::{wrap_cells, Renderer, StyledCell};
+use crate::bridge::MessageKind;
+use crate::editor::{Message, MessagesDisplay};
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+use crate::settings::*;
+
+const FADE_DURATION: Duration = Duration::from_millis(400);
+const MIN_CARD_WIDTH: u64 = 20;
+// Fraction of the editor width a notification card may take up
+const MAX_CARD_WIDTH_FRACTION: f32 = 0.4;
+// Fraction of the editor height the history panel may take up
+const MAX_HISTORY_HEIGHT_FRACTION: f32 = 0.6;
+const HISTORY_SCROLL_LINES: usize = 3;
+const ACCENT_WIDTH: f32 = 3.0;
+const BORDER_OPACITY: f32 = 0.4;
+
+#[derive(SettingGroup)]
+#[setting_prefix = "message"]
+#[derive(Clone)]
+pub struct MessageSettings {
+    duration: f32,
+    max_count: u64,
+}
+
+impl Default for MessageSettings {
+    fn default() -> Self {
+        Self {
+            duration: 5.0,
+            max_count: 5,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MessageState {
+    display: MessagesDisplay,
+    shown_at: HashMap<u64, Instant>,
+    history_scroll: usize,
+    history_region: Option<Rect>,
+}
+
+impl MessageState {
+    pub fn update(&mut self, display: MessagesDisplay) {
+        let now = Instant::now();
+        self.shown_at = display
+            .messages
+            .iter()
+            .map(|message| {
+                let shown_at = self.shown_at.get(&message.id).copied().unwrap_or(now);
+                (message.id, shown_at)
+            })
+            .collect();
+
+        // Like :messages, a new history starts out scrolled to the most recent entries
+        if display.history.is_some() && display.history != self.display.history {
+            self.history_scroll = usize::MAX;
+        }
+
+        self.display = display;
+    }
+}
+
+// Messages that wait for input stay up until neovim clears them
+fn expires(kind: MessageKind) -> bool {
+    !matches!(
+        kind,
+        MessageKind::Confirm | MessageKind::ConfirmSubstitute | MessageKind::ReturnPrompt
+    )
+}
+
+fn accent_color(kind: MessageKind, default_foreground: Color) -> Color {
+    match kind {
+        MessageKind::Error
+        | MessageKind::EchoError
+        | MessageKind::LuaError
+        | MessageKind::RpcError => Color::from_rgb(0xe0, 0x6c, 0x75),
+        MessageKind::Warning => Color::from_rgb(0xe5, 0xc0, 0x7b),
+        MessageKind::SearchCount => Color::from_rgb(0x61, 0xaf, 0xef),
+        MessageKind::Confirm | MessageKind::ConfirmSubstitute | MessageKind::ReturnPrompt => {
+            Color::from_rgb(0xc6, 0x78, 0xdd)
+        }
+        _ => default_foreground,
+    }
+}
+
+// Messages may contain line breaks, which neovim would otherwise handle while drawing the message
+// grid
+fn message_rows(content: &[StyledCell], width: usize) -> Vec<&[StyledCell]> {
+    content
+        .split(|(character, _)| character == "\n" || character == "\r\n")
+        .flat_map(|line| wrap_cells(line, width))
+        .collect()
+}
+
+impl Renderer {
+    fn draw_panel(&mut self, canvas: &mut Canvas, rect: Rect, accent: Color) {
+        let radius = self.font_width as f32 / 2.0;
+        let panel = RRect::new_rect_xy(rect, radius, radius);
+
+        let mut paint = Paint::new(self.default_style.colors.background.unwrap(), None);
+        paint.set_anti_alias(true);
+        canvas.draw_rrect(&panel, &paint);
+
+        canvas.save();
+        canvas.clip_rrect(&panel, None, Some(true));
+        paint.set_color(accent);
+        canvas.draw_rect(
+            Rect::from_xywh(rect.left, rect.top, ACCENT_WIDTH, rect.height()),
+            &paint,
+        );
+        canvas.restore();
+
+        let mut border_color = self.default_style.colors.foreground.unwrap();
+        border_color.a = BORDER_OPACITY;
+        paint.set_color(border_color.to_color());
+        paint.set_stroke(true);
+        paint.set_stroke_width(1.0);
+        canvas.draw_rrect(&panel, &paint);
+    }
+
+    fn root_grid_size(&self) -> (u64, u64) {
+        self.rendered_windows
+            .get(&1)
+            .map(|root_window| (root_window.grid_width, root_window.grid_height))
+            .unwrap_or((MIN_CARD_WIDTH, 1))
+    }
+
+    // Draws the showmode, showcmd and ruler contents over the last row of the editor, where
+    // neovim would put them without ext_messages
+    fn draw_message_strip(&mut self, canvas: &mut Canvas, display: &MessagesDisplay) {
+        if display.show_mode.is_empty()
+            && display.show_command.is_empty()
+            && display.ruler.is_empty()
+        {
+            return;
+        }
+
+        let (grid_width, grid_height) = self.root_grid_size();
+        let row = grid_height.saturating_sub(1);
+        self.draw_background(canvas, (0, row), grid_width, &None);
+
+        self.draw_styled_cells(canvas, &display.show_mode, (0, row));
+
+        let mut right_side = display.show_command.clone();
+        if !right_side.is_empty() && !display.ruler.is_empty() {
+            right_side.extend((0..2).map(|_| (" ".to_owned(), None)));
+        }
+        right_side.extend(display.ruler.iter().cloned());
+        let right_side_start = grid_width.saturating_sub(right_side.len() as u64 + 1);
+        self.draw_styled_cells(canvas, &right_side, (right_side_start, row));
+    }
+
+    // Draws the messages as cards stacked upwards from the bottom right corner, newest at the
+    // bottom, and returns the next time a card starts fading out
+    fn draw_message_cards(
+        &mut self,
+        canvas: &mut Canvas,
+        messages: &[Message],
+        shown_at: &HashMap<u64, Instant>,
+    ) -> Option<Instant> {
+        let settings = SETTINGS.get::<MessageSettings>();
+        let duration = Duration::from_secs_f32(settings.duration.max(0.0));
+        let now = Instant::now();
+        let mut next_fade = None;
+
+        let (grid_width, grid_height) = self.root_grid_size();
+        let font_width = self.font_width as f32;
+        let font_height = self.font_height as f32;
+        let padding = font_width / 2.0;
+        let max_width = ((grid_width as f32 * MAX_CARD_WIDTH_FRACTION) as u64)
+            .max(MIN_CARD_WIDTH)
+            .min(grid_width.saturating_sub(2).max(1));
+
+        let default_foreground = self.default_style.colors.foreground.unwrap().to_color();
+        let right = grid_width as f32 * font_width - font_width;
+        // Leave the last row free for the status strip
+        let mut bottom = grid_height.saturating_sub(1) as f32 * font_height - padding;
+
+        let visible_messages = messages.iter().rev().filter_map(|message| {
+            let shown_at = *shown_at.get(&message.id)?;
+            if !expires(message.kind) {
+                return Some((message, 1.0));
+            }
+
+            let fade_start = shown_at + duration;
+            let elapsed_fade = now.checked_duration_since(fade_start);
+            match elapsed_fade {
+                None => {
+                    next_fade =
+                        Some(next_fade.map_or(fade_start, |next: Instant| next.min(fade_start)));
+                    Some((message, 1.0))
+                }
+                Some(elapsed) if elapsed < FADE_DURATION => {
+                    next_fade = Some(now);
+                    Some((
+                        message,
+                        1.0 - elapsed.as_secs_f32() / FADE_DURATION.as_secs_f32(),
+                    ))
+                }
+                Some(_) => None,
+            }
+        });
+
+        for (message, opacity) in visible_messages.take(settings.max_count as usize) {
+            let width = message
+                .content
+                .split(|(character, _)| character == "\n")
+                .map(|line| line.len() as u64)
+                .max()
+                .unwrap_or(0)
+                .min(max_width)
+                .max(1);
+            let rows = message_rows(&message.content, width as usize);
+
+            let card_width = width as f32 * font_width + padding * 2.0 + ACCENT_WIDTH;
+            let card_height = rows.len() as f32 * font_height + padding * 2.0;
+            let top = bottom - card_height;
+            if top < 0.0 {
+                break;
+            }
+            let card = Rect::from_xywh(right - card_width, top, card_width, card_height);
+
+            canvas.save_layer_alpha(card, (opacity * 255.0) as u32);
+            self.draw_panel(canvas, card, accent_color(message.kind, default_foreground));
+            canvas.translate((card.left + ACCENT_WIDTH + padding, card.top + padding));
+            for (row_index, row) in rows.into_iter().enumerate() {
+                self.draw_styled_cells(canvas, row, (0, row_index as u64));
+            }
+            canvas.restore();
+
+            bottom = top - padding;
+        }
+
+        next_fade
+    }
+
+    // Draws the :messages output in a panel in the middle of the editor. Returns the region of
+    // the panel so that the mouse wheel can scroll it.
+    fn draw_message_history(
+        &mut self,
+        canvas: &mut Canvas,
+        history: &[Message],
+        scroll: &mut usize,
+    ) -> Rect {
+        let (grid_width, grid_height) = self.root_grid_size();
+        let font_width = self.font_width as f32;
+        let font_height = self.font_height as f32;
+        let padding = font_width;
+        let width = grid_width.saturating_sub(8).max(1);
+        let default_foreground = self.default_style.colors.foreground.unwrap().to_color();
+
+        let rows: Vec<(Color, &[StyledCell])> = history
+            .iter()
+            .flat_map(|message| {
+                let accent = accent_color(message.kind, default_foreground);
+                message_rows(&message.content, width as usize)
+                    .into_iter()
+                    .map(move |row| (accent, row))
+            })
+            .collect();
+
+        let max_rows = ((grid_height as f32 * MAX_HISTORY_HEIGHT_FRACTION) as usize).max(1);
+        let visible_rows = rows.len().min(max_rows);
+        *scroll = (*scroll).min(rows.len() - visible_rows);
+
+        let panel_width = width as f32 * font_width + padding * 2.0;
+        let panel_height = visible_rows as f32 * font_height + padding * 2.0;
+        let panel = Rect::from_xywh(
+            ((grid_width as f32 * font_width - panel_width) / 2.0).floor(),
+            ((grid_height as f32 * font_height - panel_height) / 2.0).floor(),
+            panel_width,
+            panel_height,
+        );
+        self.draw_panel(canvas, panel, default_foreground);
+
+        canvas.save();
+        canvas.translate((panel.left + padding, panel.top + padding));
+        let mut paint = Paint::default();
+        for (row_index, (accent, row)) in rows.iter().skip(*scroll).take(visible_rows).enumerate() {
+            paint.set_color(*accent);
+            let row_top = row_index as f32 * font_height;
+            canvas.draw_rect(
+                Rect::from_xywh(-padding / 2.0, row_top, ACCENT_WIDTH, font_height),
+                &paint,
+            );
+            self.draw_styled_cells(canvas, row, (0, row_index as u64));
+        }
+        canvas.restore();
+
+        // Show how far through the history the panel is scrolled
+        if rows.len() > visible_rows {
+            let track_height = panel_height - padding * 2.0;
+            let thumb_height = track_height * visible_rows as f32 / rows.len() as f32;
+            let thumb_top = track_height * *scroll as f32 / rows.len() as f32;
+            let mut thumb_color = self.default_style.colors.foreground.unwrap();
+            thumb_color.a = BORDER_OPACITY;
+            paint.set_color(thumb_color.to_color());
+            canvas.draw_rect(
+                Rect::from_xywh(
+                    panel.right - padding / 2.0 - ACCENT_WIDTH / 2.0,
+                    panel.top + padding + thumb_top,
+                    ACCENT_WIDTH,
+                    thumb_height,
+                ),
+                &paint,
+            );
+        }
+
+        panel
+    }
+
+    pub(super) fn draw_messages(&mut self, root_canvas: &mut Canvas) {
+        // Taken out for the duration of the draw so that the cells can be drawn with the shared
+        // helpers without cloning every message each frame
+        let mut state = std::mem::take(&mut self.messages);
+
+        self.draw_message_strip(root_canvas, &state.display);
+
+        let next_fade =
+            self.draw_message_cards(root_canvas, &state.display.messages, &state.shown_at);
+        match next_fade {
+            Some(next_fade) if next_fade <= Instant::now() => REDRAW_SCHEDULER.queue_next_frame(),
+            Some(next_fade) => REDRAW_SCHEDULER.schedule(next_fade),
+            None => {}
+        }
+
+        state.history_region = state.display.history.as_ref().map(|history| {
+            self.draw_message_history(root_canvas, history, &mut state.history_scroll)
+        });
+
+        self.messages = state;
+    }
+
+    // Scrolls the message history if it is open under the given point. Returns false if the
+    // scroll should go to neovim instead.
+    pub fn scroll_message_history(&mut self, position: Point, up: bool) -> bool {
+        match self.messages.history_region {
+            Some(region) if region.contains(position) => {
+                let scroll = &mut self.messages.history_scroll;
+                *scroll = if up {
+                    scroll.saturating_sub(HISTORY_SCROLL_LINES)
+                } else {
+                    scroll.saturating_add(HISTORY_SCROLL_LINES)
+                };
+                REDRAW_SCHEDULER.queue_next_frame();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(text: &str) -> Vec<StyledCell> {
+        text.chars()
+            .map(|character| (character.to_string(), None))
+            .collect()
+    }
+
+    #[test]
+    fn message_rows_split_lines_and_wrap() {
+        let content = cells("abcdef\ngh\n");
+        let rows = message_rows(&content, 4);
+
+        assert_eq!(
+            rows,
+            vec![
+                &cells("abcd")[..],
+                &cells("ef")[..],
+                &cells("gh")[..],
+                &cells("")[..]
+            ]
+        );
+    }
+
+    #[test]
+    fn update_keeps_shown_time_of_existing_messages() {
+        let message = |id| Message {
+            id,
+            kind: MessageKind::Echo,
+            content: cells("hi"),
+        };
+
+        let mut state = MessageState::default();
+        state.update(MessagesDisplay {
+            messages: vec![message(1)],
+            ..MessagesDisplay::default()
+        });
+        let first_shown = state.shown_at[&1];
+
+        state.update(MessagesDisplay {
+            messages: vec![message(1), message(2)],
+            ..MessagesDisplay::default()
+        });
+        assert_eq!(state.shown_at[&1], first_shown);
+        assert!(state.shown_at.contains_key(&2));
+
+        state.update(MessagesDisplay::default());
+        assert!(state.shown_at.is_empty());
+    }
+}
@@ -6,18 +6,43 @@ use log::{error, trace};
 use skia_safe::{colors, dash_path_effect, BlendMode, Canvas, Color, Paint, Rect};
 
 pub mod animation_utils;
+mod command_line;
 pub mod cursor_renderer;
 mod fonts;
+mod messages;
+mod popup_menu;
 mod rendered_window;
+mod startup_failure;
+mod tab_line;
 
 pub use fonts::caching_shaper::CachingShaper;
+pub use fonts::FontSettings;
+pub use messages::MessageSettings;
+use messages::MessageState;
+use popup_menu::PopupMenuState;
 pub use rendered_window::{RenderedWindow, WindowDrawDetails};
+pub use tab_line::TabLineTarget;
+use tab_line::TabRegion;
 
-use crate::bridge::EditorMode;
-use crate::editor::{Colors, DrawCommand, Style, WindowDrawCommand};
+use crate::bridge::{EditorMode, ScreenshotWindow, StartupFailure};
+use crate::editor::{
+    Colors, CommandLineDisplay, DrawCommand, Style, TabLineDisplay, WindowDrawCommand,
+};
 use crate::settings::*;
+use crate::window::WindowSettings;
 use cursor_renderer::CursorRenderer;
 
+type StyledCell = (String, Option<Arc<Style>>);
+
+// Splits a line into rows of at most width cells. Empty lines still take up a row.
+fn wrap_cells(line: &[StyledCell], width: usize) -> Vec<&[StyledCell]> {
+    if line.is_empty() {
+        vec![line]
+    } else {
+        line.chunks(width.max(1)).collect()
+    }
+}
+
 #[derive(SettingGroup)]
 #[setting_prefix = "window"]
 #[derive(Clone)]
@@ -42,6 +67,13 @@ impl Default for RendererSettings {
 pub struct Renderer {
     rendered_windows: HashMap<u64, RenderedWindow>,
     cursor_renderer: CursorRenderer,
+    command_line: Option<CommandLineDisplay>,
+    command_line_region: Option<Rect>,
+    messages: MessageState,
+    popup_menu: PopupMenuState,
+    tab_line: Option<TabLineDisplay>,
+    tab_regions: Vec<TabRegion>,
+    startup_failure: Option<StartupFailure>,
 
     pub current_mode: EditorMode,
     pub paint: Paint,
@@ -51,6 +83,7 @@ pub struct Renderer {
     pub font_height: u64,
     pub window_regions: Vec<WindowDrawDetails>,
     pub batched_draw_command_receiver: Receiver<Vec<DrawCommand>>,
+    pub disconnected: bool,
 }
 
 impl Renderer {
@@ -76,6 +109,13 @@ impl Renderer {
         Renderer {
             rendered_windows,
             cursor_renderer,
+            command_line: None,
+            command_line_region: None,
+            messages: MessageState::default(),
+            popup_menu: PopupMenuState::default(),
+            tab_line: None,
+            tab_regions: Vec::new(),
+            startup_failure: None,
             current_mode,
             paint,
             shaper,
@@ -84,6 +124,7 @@ impl Renderer {
             font_height,
             window_regions,
             batched_draw_command_receiver,
+            disconnected: false,
         }
     }
 
@@ -92,11 +133,31 @@ impl Renderer {
         self.update_font_dimensions();
     }
 
+    // Called by the window when g:neovide_font_* changes. Returns whether the cell size changed.
+    pub fn handle_font_settings_update(&mut self, font_settings: FontSettings) -> bool {
+        let FontSettings {
+            letter_spacing,
+            line_height,
+            features,
+        } = font_settings;
+        self.shaper.update_features(features);
+        if self.shaper.update_spacing(letter_spacing, line_height) {
+            self.update_font_dimensions();
+            return true;
+        }
+        false
+    }
+
     fn update_font(&mut self, guifont_setting: &str) {
         self.shaper.update_font(guifont_setting);
         self.update_font_dimensions();
     }
 
+    fn update_linespace(&mut self, linespace: u64) {
+        self.shaper.update_linespace(linespace);
+        self.update_font_dimensions();
+    }
+
     fn update_font_dimensions(&mut self) {
         let (font_width, font_height) = self.shaper.font_base_dimensions();
         self.font_width = font_width;
@@ -117,8 +178,19 @@ impl Renderer {
         Rect::new(x as f32, y as f32, (x + width) as f32, (y + height) as f32)
     }
 
+    fn window_transparency() -> f32 {
+        SETTINGS
+            .get::<WindowSettings>()
+            .transparency
+            .min(1.0)
+            .max(0.0)
+    }
+
+    // The default background is see through when g:neovide_transparency is set
     fn get_default_background(&self) -> Color {
-        self.default_style.colors.background.unwrap().to_color()
+        let mut background = self.default_style.colors.background.unwrap();
+        background.a *= Renderer::window_transparency();
+        background.to_color()
     }
 
     fn draw_background(
@@ -128,13 +200,19 @@ impl Renderer {
         cell_width: u64,
         style: &Option<Arc<Style>>,
     ) {
+        // Blended backgrounds replace what was drawn in the cell before instead of mixing with it,
+        // leaving see through pixels that are composited over the windows beneath along with the
+        // rest of the grid. The text drawn on top with the same paint stays opaque.
         self.paint.set_blend_mode(BlendMode::Src);
 
         let region = self.compute_text_region(grid_pos, cell_width);
         let style = style.as_ref().unwrap_or(&self.default_style);
 
-        self.paint
-            .set_color(style.background(&self.default_style.colors).to_color());
+        let mut background = style.blended_background(&self.default_style.colors);
+        if Arc::ptr_eq(style, &self.default_style) || style.uses_default_background() {
+            background.a *= Renderer::window_transparency();
+        }
+        self.paint.set_color(background.to_color());
         canvas.draw_rect(region, &self.paint);
     }
 
@@ -213,6 +291,34 @@ impl Renderer {
         canvas.restore();
     }
 
+    // Draws a row of cells that did not come from a grid, such as the command line, starting at the
+    // given grid position of the canvas
+    fn draw_styled_cells(
+        &mut self,
+        canvas: &mut Canvas,
+        row: &[StyledCell],
+        (grid_left, grid_top): (u64, u64),
+    ) {
+        let mut start = 0;
+        while start < row.len() {
+            let style = &row[start].1;
+            let length = row[start..]
+                .iter()
+                .take_while(|(_, cell_style)| cell_style == style)
+                .count();
+            let cells: Vec<String> = row[start..start + length]
+                .iter()
+                .map(|(character, _)| character.clone())
+                .collect();
+
+            let grid_position = (grid_left + start as u64, grid_top);
+            self.draw_background(canvas, grid_position, length as u64, style);
+            self.draw_foreground(canvas, &cells, grid_position, length as u64, style);
+
+            start += length;
+        }
+    }
+
     pub fn handle_draw_command(&mut self, root_canvas: &mut Canvas, draw_command: DrawCommand) {
         match draw_command {
             DrawCommand::Window {
@@ -228,10 +334,11 @@ impl Renderer {
                 } else if let WindowDrawCommand::Position {
                     grid_position: (grid_left, grid_top),
                     grid_size: (width, height),
+                    window_handle,
                     ..
                 } = command
                 {
-                    let new_window = RenderedWindow::new(
+                    let mut new_window = RenderedWindow::new(
                         root_canvas,
                         self,
                         grid_id,
@@ -239,6 +346,7 @@ impl Renderer {
                         width,
                         height,
                     );
+                    new_window.window_handle = window_handle;
                     self.rendered_windows.insert(grid_id, new_window);
                 } else {
                     error!("WindowDrawCommand sent for uninitialized grid {}", grid_id);
@@ -250,19 +358,67 @@ impl Renderer {
             DrawCommand::FontChanged(new_font) => {
                 self.update_font(&new_font);
             }
+            DrawCommand::LineSpaceChanged(linespace) => {
+                self.update_linespace(linespace);
+            }
             DrawCommand::DefaultStyleChanged(new_style) => {
                 self.default_style = Arc::new(new_style);
             }
             DrawCommand::ModeChanged(new_mode) => {
                 self.current_mode = new_mode;
             }
+            DrawCommand::CommandLine(command_line) => {
+                self.command_line = command_line;
+            }
+            DrawCommand::Messages(messages) => {
+                self.messages.update(messages);
+            }
+            DrawCommand::PopupMenu(popup_menu) => {
+                self.popup_menu.update(popup_menu);
+            }
+            DrawCommand::TabLine(tab_line) => {
+                self.tab_line = tab_line;
+            }
+            DrawCommand::Disconnected(disconnected) => {
+                self.disconnected = disconnected;
+            }
+            DrawCommand::StartupFailure(startup_failure) => {
+                self.startup_failure = startup_failure;
+            }
             _ => {}
         }
     }
 
+    // Where the current neovim window was drawn. With multigrid it has a grid of its own, found
+    // by the window handle, whose drawn position includes animations. Otherwise it is part of the
+    // root grid, so its cells are used.
+    pub fn current_window_region(&self, window: ScreenshotWindow) -> Rect {
+        let rendered_window = self
+            .rendered_windows
+            .values()
+            .find(|rendered_window| rendered_window.window_handle == Some(window.id));
+        match rendered_window {
+            Some(rendered_window) if rendered_window.id != 1 => rendered_window
+                .pixel_region(self.font_width, self.font_height)
+                .with_offset((0.0, self.tab_line_height() as f32)),
+            _ => self.cell_region(window.cells),
+        }
+    }
+
+    // The pixels covered by a block of cells of the main grid
+    pub fn cell_region(&self, (column, row, width, height): (u64, u64, u64, u64)) -> Rect {
+        Rect::from_xywh(
+            (column * self.font_width) as f32,
+            (row * self.font_height + self.tab_line_height()) as f32,
+            (width * self.font_width) as f32,
+            (height * self.font_height) as f32,
+        )
+    }
+
     #[allow(clippy::needless_collect)]
     pub fn draw_frame(&mut self, root_canvas: &mut Canvas, dt: f32) -> bool {
-        let mut font_changed = false;
+        let mut grid_size_changed = false;
+        let previous_tab_line_height = self.tab_line_height();
 
         let draw_commands: Vec<_> = self
             .batched_draw_command_receiver
@@ -272,19 +428,32 @@ impl Renderer {
             .collect();
 
         for draw_command in draw_commands.into_iter() {
-            if let DrawCommand::FontChanged(_) = draw_command {
-                font_changed = true;
+            if matches!(
+                draw_command,
+                DrawCommand::FontChanged(_) | DrawCommand::LineSpaceChanged(_)
+            ) {
+                grid_size_changed = true;
             }
             self.handle_draw_command(root_canvas, draw_command);
         }
 
-        root_canvas.clear(self.default_style.colors.background.unwrap().to_color());
+        // Showing or hiding the tab line changes how many rows fit in the window
+        let tab_line_height = self.tab_line_height();
+        if tab_line_height != previous_tab_line_height {
+            grid_size_changed = true;
+        }
+
+        root_canvas.clear(self.get_default_background());
         root_canvas.save();
         root_canvas.reset_matrix();
+        root_canvas.translate((0.0, tab_line_height as f32));
 
         if let Some(root_window) = self.rendered_windows.get(&1) {
             let clip_rect = root_window.pixel_region(self.font_width, self.font_height);
             root_canvas.clip_rect(&clip_rect, None, Some(false));
+            // The windows draw the see through background themselves, so it would be applied twice
+            // if it was left under them
+            root_canvas.clear(Color::TRANSPARENT);
         }
 
         let default_background = self.get_default_background();
@@ -332,9 +501,16 @@ impl Renderer {
             })
             .collect();
 
+        self.draw_messages(root_canvas);
+        let command_line_cursor = self.draw_command_line(root_canvas);
+        self.draw_popup_menu(root_canvas, dt);
+
         let windows = &self.rendered_windows;
         self.cursor_renderer
             .update_cursor_destination(font_width, font_height, windows);
+        if let Some(command_line_cursor) = command_line_cursor {
+            self.cursor_renderer.set_destination(command_line_cursor);
+        }
 
         self.cursor_renderer.draw(
             &self.default_style.colors,
@@ -347,6 +523,38 @@ impl Renderer {
 
         root_canvas.restore();
 
-        font_changed
+        self.draw_tab_line(root_canvas);
+
+        if self.disconnected {
+            self.draw_disconnected_overlay(root_canvas);
+        }
+
+        self.draw_startup_failure(root_canvas);
+
+        grid_size_changed
+    }
+
+    fn draw_disconnected_overlay(&mut self, root_canvas: &mut Canvas) {
+        let size = root_canvas.base_layer_size();
+        let mut paint = Paint::new(colors::WHITE, None);
+
+        // Dim the last frame so it is obvious the contents are stale
+        paint.set_color(Color::from_argb(160, 0, 0, 0));
+        root_canvas.draw_rect(Rect::from_wh(size.width as f32, size.height as f32), &paint);
+
+        let message = "Connection to neovim lost, reconnecting...";
+        let cells: Vec<String> = message
+            .chars()
+            .map(|character| character.to_string())
+            .collect();
+        let text_width = cells.len() as u64 * self.font_width;
+        let x = (size.width as f32 - text_width as f32).max(0.0) / 2.0;
+        let y = (size.height as f32 - self.font_height as f32).max(0.0) / 2.0;
+        let y_adjustment = self.shaper.y_adjustment();
+
+        paint.set_color(colors::WHITE);
+        for blob in self.shaper.shape_cached(&cells, false, false).iter() {
+            root_canvas.draw_text_blob(blob, (x, y + y_adjustment as f32), &paint);
+        }
     }
 }
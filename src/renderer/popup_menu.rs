@@ -0,0 +1,368 @@
+use std::sync::Arc;
+
+use skia_safe::{Canvas, Color, Paint, Point, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::animation_utils::{ease, ease_out_expo};
+use super::Renderer;
+use crate::bridge::PopupMenuItem;
+use crate::editor::{PopupMenuAnchor, PopupMenuDisplay, Style};
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+const MAX_VISIBLE_ITEMS: usize = 15;
+const ANIMATION_LENGTH: f32 = 0.1;
+
+// A value that eases towards its destination over ANIMATION_LENGTH seconds
+#[derive(Default)]
+struct AnimatedValue {
+    start: f32,
+    destination: f32,
+    current: f32,
+    t: f32,
+}
+
+impl AnimatedValue {
+    fn jump_to(&mut self, value: f32) {
+        self.start = value;
+        self.destination = value;
+        self.current = value;
+        self.t = 1.0;
+    }
+
+    fn set_destination(&mut self, value: f32) {
+        if (self.destination - value).abs() > std::f32::EPSILON {
+            self.start = self.current;
+            self.destination = value;
+            self.t = 0.0;
+        }
+    }
+
+    // Returns true while the value is still moving
+    fn update(&mut self, dt: f32) -> bool {
+        if self.t >= 1.0 {
+            return false;
+        }
+
+        self.t = (self.t + dt / ANIMATION_LENGTH).min(1.0);
+        self.current = ease(ease_out_expo, self.start, self.destination, self.t);
+        true
+    }
+}
+
+#[derive(Default)]
+pub struct PopupMenuState {
+    display: Option<PopupMenuDisplay>,
+    // Set when a new list of items arrives so that the first frame doesn't animate
+    fresh: bool,
+    scroll_top: usize,
+    scroll: AnimatedValue,
+    selection: AnimatedValue,
+    region: Option<Rect>,
+}
+
+impl PopupMenuState {
+    pub fn update(&mut self, display: Option<PopupMenuDisplay>) {
+        let same_items = match (&self.display, &display) {
+            (Some(current), Some(new)) => Arc::ptr_eq(&current.items, &new.items),
+            _ => false,
+        };
+
+        if !same_items {
+            self.fresh = true;
+            self.scroll_top = 0;
+        }
+
+        if display.is_none() {
+            self.region = None;
+        }
+
+        self.display = display;
+    }
+}
+
+// Widths of the word, kind and menu columns. Columns without any text get a width of zero and
+// are left out entirely.
+fn column_widths(items: &[PopupMenuItem]) -> [usize; 3] {
+    let mut widths = [0; 3];
+    for item in items {
+        for (width, text) in widths
+            .iter_mut()
+            .zip([&item.word, &item.kind, &item.menu].iter())
+        {
+            *width = (*width).max(text.graphemes(true).count());
+        }
+    }
+    widths
+}
+
+// Lays out an item as a row of cells with a one cell gap on either side of every column
+fn item_cells(item: &PopupMenuItem, widths: &[usize; 3]) -> Vec<String> {
+    let mut cells = vec![" ".to_owned()];
+    for (width, text) in widths
+        .iter()
+        .zip([&item.word, &item.kind, &item.menu].iter())
+    {
+        if *width == 0 {
+            continue;
+        }
+
+        let column: Vec<String> = text.graphemes(true).map(str::to_owned).collect();
+        let padding = width - column.len();
+        cells.extend(column);
+        cells.extend((0..=padding).map(|_| " ".to_owned()));
+    }
+    cells
+}
+
+// Returns the first visible item after scrolling as little as possible to show the selection
+fn scroll_to_show(scroll_top: usize, selected: Option<u64>, visible_count: usize) -> usize {
+    match selected {
+        Some(selected) if (selected as usize) < scroll_top => selected as usize,
+        Some(selected) if selected as usize >= scroll_top + visible_count => {
+            selected as usize + 1 - visible_count
+        }
+        _ => scroll_top,
+    }
+}
+
+// Puts the menu below the anchor line if it fits, otherwise above it if there is more room there.
+// The menu is shifted left rather than running off the right edge.
+fn place_menu(anchor: Point, line_height: f32, size: (f32, f32), bounds: (f32, f32)) -> Point {
+    let (width, height) = size;
+    let (bounds_width, bounds_height) = bounds;
+
+    let below = anchor.y + line_height;
+    let y = if below + height > bounds_height && anchor.y - height >= 0.0 {
+        anchor.y - height
+    } else {
+        below
+    };
+    let x = anchor.x.min(bounds_width - width).max(0.0);
+
+    Point::new(x, y)
+}
+
+// pumblend only applies to the backgrounds so that the text stays readable
+fn blended_background(style: &Option<Arc<Style>>, default_style: &Style, opacity: f32) -> Color {
+    let mut color = style
+        .as_deref()
+        .unwrap_or(default_style)
+        .background(&default_style.colors);
+    color.a *= opacity;
+    color.to_color()
+}
+
+impl Renderer {
+    pub(super) fn draw_popup_menu(&mut self, root_canvas: &mut Canvas, dt: f32) {
+        let mut state = std::mem::take(&mut self.popup_menu);
+        if let Some(display) = state.display.clone() {
+            state.region = self.draw_popup_menu_items(root_canvas, &display, &mut state, dt);
+        }
+        self.popup_menu = state;
+    }
+
+    fn draw_popup_menu_items(
+        &mut self,
+        root_canvas: &mut Canvas,
+        display: &PopupMenuDisplay,
+        state: &mut PopupMenuState,
+        dt: f32,
+    ) -> Option<Rect> {
+        if display.items.is_empty() {
+            return None;
+        }
+
+        let font_width = self.font_width as f32;
+        let font_height = self.font_height as f32;
+        let size = root_canvas.base_layer_size();
+        let bounds = (
+            size.width as f32,
+            size.height as f32 - self.tab_line_height() as f32,
+        );
+
+        // The leading gap of the word column is placed left of the anchor so that the completions
+        // line up with the text being completed
+        let anchor = match display.anchor {
+            PopupMenuAnchor::Grid { left, top } => {
+                Point::new((left as f32 - 1.0) * font_width, top as f32 * font_height)
+            }
+            PopupMenuAnchor::CommandLine { column } => {
+                let region = self.command_line_region?;
+                Point::new(
+                    region.left + column as f32 * font_width,
+                    region.bottom - font_height,
+                )
+            }
+        };
+
+        let rows_below = ((bounds.1 - anchor.y) / font_height) as usize;
+        let rows_above = (anchor.y / font_height) as usize;
+        let visible_count = display
+            .items
+            .len()
+            .min(MAX_VISIBLE_ITEMS)
+            .min(rows_below.saturating_sub(1).max(rows_above))
+            .max(1);
+
+        let widths = column_widths(&display.items);
+        let rows: Vec<Vec<String>> = display
+            .items
+            .iter()
+            .map(|item| item_cells(item, &widths))
+            .collect();
+        let width = rows[0].len().min((bounds.0 / font_width) as usize).max(1);
+
+        let menu_size = (
+            width as f32 * font_width,
+            visible_count as f32 * font_height,
+        );
+        let position = place_menu(anchor, font_height, menu_size, bounds);
+        let region = Rect::from_point_and_size(position, menu_size);
+
+        let max_scroll_top = display.items.len() - visible_count;
+        state.scroll_top =
+            scroll_to_show(state.scroll_top, display.selected, visible_count).min(max_scroll_top);
+        let selected_row = display.selected.unwrap_or(0) as f32;
+        if std::mem::take(&mut state.fresh) {
+            state.scroll.jump_to(state.scroll_top as f32);
+            state.selection.jump_to(selected_row);
+        } else {
+            state.scroll.set_destination(state.scroll_top as f32);
+            state.selection.set_destination(selected_row);
+        }
+        if state.scroll.update(dt) | state.selection.update(dt) {
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+
+        let opacity = 1.0 - display.blend.min(100) as f32 / 100.0;
+        let background_color =
+            |style: &Option<Arc<Style>>| blended_background(style, &self.default_style, opacity);
+        let background = background_color(&display.style);
+        let selected_background = background_color(&display.selected_style);
+        let thumb_background = background_color(&display.thumb_style);
+        let mut paint = Paint::new(self.default_style.colors.background.unwrap(), None);
+        paint.set_anti_alias(false);
+
+        root_canvas.save();
+        root_canvas.clip_rect(&region, None, Some(false));
+
+        paint.set_color(background);
+        root_canvas.draw_rect(region, &paint);
+
+        root_canvas.translate((position.x, position.y - state.scroll.current * font_height));
+
+        if display.selected.is_some() {
+            paint.set_color(selected_background);
+            root_canvas.draw_rect(
+                Rect::from_xywh(
+                    0.0,
+                    state.selection.current * font_height,
+                    width as f32 * font_width,
+                    font_height,
+                ),
+                &paint,
+            );
+        }
+
+        // Only the rows that can be seen while scrolling need to be shaped
+        let first_row = state.scroll.current.floor() as usize;
+        let last_row = (first_row + visible_count + 1).min(rows.len());
+        for (index, row) in rows.iter().enumerate().take(last_row).skip(first_row) {
+            let style = if display.selected == Some(index as u64) {
+                &display.selected_style
+            } else {
+                &display.style
+            };
+            let cells = &row[..row.len().min(width)];
+            self.draw_foreground(
+                root_canvas,
+                cells,
+                (0, index as u64),
+                cells.len() as u64,
+                style,
+            );
+        }
+        root_canvas.restore();
+
+        if display.items.len() > visible_count {
+            let thumb_height = menu_size.1 * visible_count as f32 / display.items.len() as f32;
+            let thumb_top = region.top
+                + (menu_size.1 - thumb_height) * state.scroll.current / max_scroll_top as f32;
+            paint.set_color(thumb_background);
+            root_canvas.draw_rect(
+                Rect::from_xywh(
+                    region.right - font_width / 2.0,
+                    thumb_top,
+                    font_width / 2.0,
+                    thumb_height,
+                ),
+                &paint,
+            );
+        }
+
+        Some(region)
+    }
+
+    // Returns the index of the popup menu item drawn under the given point, if any
+    pub fn popup_menu_item_at(&self, position: Point) -> Option<u64> {
+        let display = self.popup_menu.display.as_ref()?;
+        let region = self.popup_menu.region?;
+        if !region.contains(position) {
+            return None;
+        }
+
+        let row =
+            (position.y - region.top) / self.font_height as f32 + self.popup_menu.scroll.current;
+        let item = row.floor() as usize;
+        if item < display.items.len() {
+            Some(item as u64)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(word: &str, kind: &str, menu: &str) -> PopupMenuItem {
+        PopupMenuItem {
+            word: word.to_owned(),
+            kind: kind.to_owned(),
+            menu: menu.to_owned(),
+            info: "".to_owned(),
+        }
+    }
+
+    #[test]
+    fn empty_columns_are_left_out() {
+        let items = vec![item("foo", "", "[LSP]"), item("barbaz", "", "")];
+        let widths = column_widths(&items);
+        assert_eq!(widths, [6, 0, 5]);
+
+        assert_eq!(item_cells(&items[0], &widths).concat(), " foo    [LSP] ");
+        assert_eq!(item_cells(&items[1], &widths).concat(), " barbaz       ");
+    }
+
+    #[test]
+    fn scrolling_follows_the_selection() {
+        assert_eq!(scroll_to_show(0, Some(3), 5), 0);
+        assert_eq!(scroll_to_show(0, Some(7), 5), 3);
+        assert_eq!(scroll_to_show(3, Some(1), 5), 1);
+        assert_eq!(scroll_to_show(3, None, 5), 3);
+    }
+
+    #[test]
+    fn menu_flips_above_when_there_is_no_room_below() {
+        let bounds = (100.0, 100.0);
+        assert_eq!(
+            place_menu(Point::new(10.0, 20.0), 10.0, (30.0, 40.0), bounds),
+            Point::new(10.0, 30.0)
+        );
+        assert_eq!(
+            place_menu(Point::new(90.0, 70.0), 10.0, (30.0, 40.0), bounds),
+            Point::new(70.0, 30.0)
+        );
+    }
+}
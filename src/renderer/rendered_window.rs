@@ -95,6 +95,7 @@ pub struct RenderedWindow {
     pub current_surface: LocatedSurface,
 
     pub id: u64,
+    pub window_handle: Option<u64>,
     pub hidden: bool,
     pub floating_order: Option<u64>,
 
@@ -115,6 +116,7 @@ pub struct RenderedWindow {
 #[derive(Clone, Debug)]
 pub struct WindowDrawDetails {
     pub id: u64,
+    pub window_handle: Option<u64>,
     pub region: Rect,
     pub floating_order: Option<u64>,
 }
@@ -135,6 +137,7 @@ impl RenderedWindow {
             snapshots: VecDeque::new(),
             current_surface,
             id,
+            window_handle: None,
             hidden: false,
             floating_order: None,
 
@@ -247,7 +250,9 @@ impl RenderedWindow {
             a = (settings.floating_opacity.min(1.0).max(0.0) * 255.0) as u8;
         }
 
-        paint.set_color(default_background.with_a(a));
+        // The default background may already be see through from the window transparency
+        let background_alpha = (default_background.a() as u32 * a as u32 / 255) as u8;
+        paint.set_color(default_background.with_a(background_alpha));
         root_canvas.draw_rect(pixel_region, &paint);
 
         paint.set_color(Color::from_argb(a, 255, 255, 255));
@@ -285,6 +290,7 @@ impl RenderedWindow {
 
         WindowDrawDetails {
             id: self.id,
+            window_handle: self.window_handle,
             region: pixel_region,
             floating_order: self.floating_order,
         }
@@ -300,6 +306,7 @@ impl RenderedWindow {
                 grid_position: (grid_left, grid_top),
                 grid_size: (grid_width, grid_height),
                 floating_order,
+                window_handle,
             } => {
                 let new_destination: Point = (grid_left as f32, grid_top as f32).into();
 
@@ -318,7 +325,15 @@ impl RenderedWindow {
                     self.grid_destination = new_destination;
                 }
 
-                if grid_width != self.grid_width || grid_height != self.grid_height {
+                // Changes to the cell size resize the surface too, even when the grid stays the same
+                let pixel_size_changed = self.current_surface.surface.width() as u64
+                    != grid_width * renderer.font_width
+                    || self.current_surface.surface.height() as u64
+                        != grid_height * renderer.font_height;
+                if grid_width != self.grid_width
+                    || grid_height != self.grid_height
+                    || pixel_size_changed
+                {
                     let mut old_surface = self.current_surface.surface;
                     self.current_surface.surface = build_window_surface_with_grid_size(
                         old_surface.canvas(),
@@ -338,6 +353,7 @@ impl RenderedWindow {
                 }
 
                 self.floating_order = floating_order;
+                self.window_handle = window_handle;
 
                 if self.hidden {
                     self.hidden = false;
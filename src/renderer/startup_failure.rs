@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use skia_safe::{Canvas, Color4f};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{wrap_cells, Renderer, StyledCell};
+use crate::bridge::StartupFailure;
+use crate::editor::{Colors, Style};
+
+const TITLE_COLOR: Color4f = Color4f {
+    r: 0.9,
+    g: 0.35,
+    b: 0.35,
+    a: 1.0,
+};
+const MARGIN: u64 = 2;
+
+fn cells(text: &str, style: &Option<Arc<Style>>) -> Vec<StyledCell> {
+    text.graphemes(true)
+        .map(|grapheme| (grapheme.to_owned(), style.clone()))
+        .collect()
+}
+
+fn wrap_rows(lines: &[Vec<StyledCell>], width: usize) -> Vec<Vec<StyledCell>> {
+    lines
+        .iter()
+        .flat_map(|line| wrap_cells(line, width))
+        .map(|row| row.to_vec())
+        .collect()
+}
+
+// Lays out the error screen in rows of at most width cells. When it doesn't fit, the oldest
+// stderr output is left out since the last lines usually hold the actual error.
+fn failure_rows(
+    failure: &StartupFailure,
+    title_style: &Option<Arc<Style>>,
+    (width, height): (usize, usize),
+) -> Vec<Vec<StyledCell>> {
+    let header = vec![
+        cells("Neovide could not start neovim", title_style),
+        Vec::new(),
+        cells(&failure.reason.to_string(), &None),
+        Vec::new(),
+        cells(&format!("Command: {}", failure.command), &None),
+    ];
+    let mut stderr = Vec::new();
+    if !failure.stderr.is_empty() {
+        stderr.push(Vec::new());
+        stderr.push(cells("Neovim's output:", title_style));
+        stderr.extend(failure.stderr.iter().map(|line| cells(line, &None)));
+    }
+    let footer = vec![
+        Vec::new(),
+        cells("Press r to retry or q to quit", title_style),
+    ];
+
+    let mut rows = wrap_rows(&header, width);
+    let footer = wrap_rows(&footer, width);
+    let stderr = wrap_rows(&stderr, width);
+    let stderr_space = height.saturating_sub(rows.len() + footer.len());
+    rows.extend(stderr.into_iter().rev().take(stderr_space).rev());
+    rows.extend(footer);
+    rows
+}
+
+impl Renderer {
+    pub(super) fn draw_startup_failure(&mut self, root_canvas: &mut Canvas) {
+        let failure = match self.startup_failure.as_ref() {
+            Some(failure) => failure,
+            None => return,
+        };
+
+        // The screen replaces whatever was drawn before, since there is no session to show
+        let size = root_canvas.base_layer_size();
+        let default_background = self.get_default_background();
+        root_canvas.save();
+        root_canvas.reset_matrix();
+        // Cleared rather than drawn over, so a see through background isn't applied twice
+        root_canvas.clear(default_background);
+
+        let grid_width = size.width as u64 / self.font_width;
+        let grid_height = size.height as u64 / self.font_height;
+        let title_style = Some(Arc::new(Style::new(Colors::new(
+            Some(TITLE_COLOR),
+            None,
+            None,
+        ))));
+        let rows = failure_rows(
+            failure,
+            &title_style,
+            (
+                grid_width.saturating_sub(MARGIN * 2).max(1) as usize,
+                grid_height.saturating_sub(MARGIN * 2) as usize,
+            ),
+        );
+
+        for (index, row) in rows.iter().enumerate() {
+            self.draw_styled_cells(root_canvas, row, (MARGIN, MARGIN + index as u64));
+        }
+        root_canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::StartupFailureReason;
+
+    fn text(row: &[StyledCell]) -> String {
+        row.iter()
+            .map(|(character, _)| character.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn stderr_is_trimmed_to_the_last_lines() {
+        let failure = StartupFailure {
+            reason: StartupFailureReason::NotFound,
+            command: "nvim --embed".to_owned(),
+            stderr: (0..10).map(|index| format!("line {}", index)).collect(),
+        };
+
+        let rows = failure_rows(&failure, &None, (80, 12));
+        let rows: Vec<String> = rows.iter().map(|row| text(row)).collect();
+        assert_eq!(rows.len(), 12);
+        assert_eq!(rows[4], "Command: nvim --embed");
+        assert_eq!(rows[5], "line 5");
+        assert_eq!(rows[9], "line 9");
+        assert_eq!(rows[11], "Press r to retry or q to quit");
+    }
+}
@@ -0,0 +1,171 @@
+use std::ops::Range;
+use std::path::Path;
+
+use rmpv::Value;
+use skia_safe::{Canvas, Point, Rect};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{Renderer, StyledCell};
+
+const MIN_TAB_WIDTH: usize = 8;
+const MAX_TAB_WIDTH: usize = 30;
+const CLOSE_BUTTON: &str = "×";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TabLineTarget {
+    Tab(Value),
+    CloseButton(Value),
+}
+
+pub struct TabRegion {
+    tab: Value,
+    region: Rect,
+    close_region: Rect,
+}
+
+// Neovim sends the full name of the buffer in the current window of each tab
+fn tab_title(name: &str) -> String {
+    if name.is_empty() {
+        return "[No Name]".to_owned();
+    }
+
+    Path::new(name)
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| name.to_owned())
+}
+
+// Lays out a tab as " title × " in at most width cells, cutting the title short if needed
+fn tab_cells(title: &str, width: usize) -> Vec<String> {
+    let mut title: Vec<String> = title.graphemes(true).map(str::to_owned).collect();
+    let max_title_width = width.saturating_sub(4).max(1);
+    if title.len() > max_title_width {
+        title.truncate(max_title_width - 1);
+        title.push("…".to_owned());
+    }
+
+    let mut cells = vec![" ".to_owned()];
+    cells.extend(title);
+    cells.extend(vec![
+        " ".to_owned(),
+        CLOSE_BUTTON.to_owned(),
+        " ".to_owned(),
+    ]);
+    cells
+}
+
+// When the tabs don't fit at their smallest, the tab line scrolls to show the ones around the
+// current tab
+fn visible_tabs(tab_count: usize, current_index: usize, max_visible: usize) -> Range<usize> {
+    let max_visible = max_visible.max(1);
+    if tab_count <= max_visible {
+        return 0..tab_count;
+    }
+
+    let start = current_index
+        .saturating_sub(max_visible / 2)
+        .min(tab_count - max_visible);
+    start..start + max_visible
+}
+
+impl Renderer {
+    // The tab line takes up a row above the grids while it is shown
+    pub fn tab_line_height(&self) -> u64 {
+        if self.tab_line.is_some() {
+            self.font_height
+        } else {
+            0
+        }
+    }
+
+    pub(super) fn draw_tab_line(&mut self, root_canvas: &mut Canvas) {
+        self.tab_regions.clear();
+        let tab_line = match self.tab_line.clone() {
+            Some(tab_line) => tab_line,
+            None => return,
+        };
+
+        let grid_width = root_canvas.base_layer_size().width as u64 / self.font_width;
+        self.draw_background(root_canvas, (0, 0), grid_width, &tab_line.fill_style);
+
+        let tab_width = (grid_width as usize / tab_line.tabs.len().max(1))
+            .min(MAX_TAB_WIDTH)
+            .max(MIN_TAB_WIDTH);
+        let current_index = tab_line
+            .tabs
+            .iter()
+            .position(|tab| tab.tab == tab_line.current_tab)
+            .unwrap_or(0);
+        let visible = visible_tabs(
+            tab_line.tabs.len(),
+            current_index,
+            grid_width as usize / tab_width,
+        );
+
+        let mut column = 0;
+        for tab in tab_line.tabs[visible].iter() {
+            let style = if tab.tab == tab_line.current_tab {
+                &tab_line.selected_style
+            } else {
+                &tab_line.style
+            };
+            let cells: Vec<StyledCell> = tab_cells(&tab_title(&tab.name), tab_width)
+                .into_iter()
+                .map(|cell| (cell, style.clone()))
+                .collect();
+            let width = cells.len() as u64;
+            self.draw_styled_cells(root_canvas, &cells, (column, 0));
+
+            self.tab_regions.push(TabRegion {
+                tab: tab.tab.clone(),
+                region: self.compute_text_region((column, 0), width),
+                close_region: self.compute_text_region((column + width - 2, 0), 1),
+            });
+            column += width;
+        }
+    }
+
+    // Returns what a click at the given window position would hit in the tab line
+    pub fn tab_line_target_at(&self, position: Point) -> Option<TabLineTarget> {
+        let tab_region = self
+            .tab_regions
+            .iter()
+            .find(|tab_region| tab_region.region.contains(position))?;
+
+        if tab_region.close_region.contains(position) {
+            Some(TabLineTarget::CloseButton(tab_region.tab.clone()))
+        } else {
+            Some(TabLineTarget::Tab(tab_region.tab.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_use_the_file_name() {
+        assert_eq!(tab_title("/home/user/src/main.rs"), "main.rs");
+        assert_eq!(tab_title(""), "[No Name]");
+        assert_eq!(tab_title("term://~//1234:bash"), "1234:bash");
+    }
+
+    #[test]
+    fn tabs_scroll_to_keep_the_current_one_visible() {
+        assert_eq!(visible_tabs(3, 2, 10), 0..3);
+        assert_eq!(visible_tabs(20, 0, 5), 0..5);
+        assert_eq!(visible_tabs(20, 10, 5), 8..13);
+        assert_eq!(visible_tabs(20, 19, 5), 15..20);
+        assert_eq!(visible_tabs(20, 4, 0), 4..5);
+    }
+
+    #[test]
+    fn long_titles_are_cut_short() {
+        assert_eq!(tab_cells("main.rs", 20).concat(), " main.rs × ");
+        assert_eq!(
+            tab_cells("a_very_long_name.rs", 12).concat(),
+            " a_very_… × "
+        );
+    }
+}
@@ -248,7 +248,10 @@ mod tests {
         //TODO: this sets a static variable. Can this have side effects on other tests?
         SETTINGS.set::<CmdLineSettings>(&CmdLineSettings::default());
 
-        let (nvim, _) = create::new_child_cmd(&mut create_nvim_command(), NeovimHandler())
+        let mut command = tokio::process::Command::from(
+            create_nvim_command().expect("Could not locate the neovim process"),
+        );
+        let (nvim, _, _) = create::new_child_cmd(&mut command, NeovimHandler())
             .await
             .unwrap_or_explained_panic("Could not locate or start the neovim process");
         nvim.set_var(&v4, Value::from(v2.clone())).await.ok();
@@ -1,18 +1,22 @@
 mod settings;
+mod window_info;
 mod window_wrapper;
 
 use crate::{
     bridge::UiCommand,
     channel_utils::*,
+    cmd_line::CmdLineSettings,
     editor::{DrawCommand, WindowCommand},
     renderer::Renderer,
+    settings::SETTINGS,
 };
 use glutin::dpi::PhysicalSize;
 use std::sync::{atomic::AtomicBool, mpsc::Receiver, Arc};
 
-pub use window_wrapper::start_loop;
+pub use window_wrapper::{start_loop, start_offscreen_loop};
 
 pub use settings::*;
+pub use window_info::{MonitorInfo, WindowInfo, WINDOW_INFO};
 
 #[cfg(target_os = "windows")]
 fn windows_fix_dpi() {
@@ -31,7 +35,10 @@ fn handle_new_grid_size(
     if new_size.width > 0 && new_size.height > 0 {
         // Add 1 here to make sure resizing doesn't change the grid size on startup
         let width = ((new_size.width + 1) / renderer.font_width as u32) as u32;
-        let height = ((new_size.height + 1) / renderer.font_height as u32) as u32;
+        let grid_pixel_height = new_size
+            .height
+            .saturating_sub(renderer.tab_line_height() as u32);
+        let height = ((grid_pixel_height + 1) / renderer.font_height as u32) as u32;
         ui_command_sender
             .send(UiCommand::Resize { width, height })
             .ok();
@@ -43,7 +50,17 @@ pub fn create_window(
     window_command_receiver: Receiver<WindowCommand>,
     ui_command_sender: LoggingTx<UiCommand>,
     running: Arc<AtomicBool>,
-) {
+) -> Result<(), String> {
+    if SETTINGS.get::<CmdLineSettings>().offscreen {
+        start_offscreen_loop(
+            batched_draw_command_receiver,
+            window_command_receiver,
+            ui_command_sender,
+            running,
+        );
+        return Ok(());
+    }
+
     #[cfg(target_os = "windows")]
     windows_fix_dpi();
 
@@ -52,5 +69,5 @@ pub fn create_window(
         window_command_receiver,
         ui_command_sender,
         running,
-    );
+    )
 }
@@ -1,4 +1,4 @@
-use crate::{cmd_line::CmdLineSettings, settings::*};
+use crate::{bridge::OpenMode, cmd_line::CmdLineSettings, settings::*};
 
 #[derive(Clone, SettingGroup)]
 pub struct WindowSettings {
@@ -8,6 +8,7 @@ pub struct WindowSettings {
     pub fullscreen: bool,
     pub iso_layout: bool,
     pub scroll_dead_zone: f32,
+    pub file_drop_action: OpenMode,
 }
 
 impl Default for WindowSettings {
@@ -22,6 +23,7 @@ impl Default for WindowSettings {
                 .neovim_args
                 .contains(&String::from("--noIdle")),
             scroll_dead_zone: 0.0,
+            file_drop_action: OpenMode::Edit,
         }
     }
 }
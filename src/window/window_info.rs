@@ -0,0 +1,23 @@
+use parking_lot::RwLock;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MonitorInfo {
+    pub name: String,
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    pub scale_factor: f64,
+}
+
+// A snapshot of the window for answering requests from neovim, which arrive on the bridge's
+// runtime rather than the window thread
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WindowInfo {
+    pub size: (u32, u32),
+    pub cell_size: (u64, u64),
+    pub scale_factor: f64,
+    pub monitors: Vec<MonitorInfo>,
+}
+
+lazy_static! {
+    pub static ref WINDOW_INFO: RwLock<WindowInfo> = RwLock::new(WindowInfo::default());
+}
@@ -1,6 +1,10 @@
 mod keyboard_manager;
 mod mouse_manager;
+mod offscreen;
 mod renderer;
+mod screencast;
+mod screenshot;
+mod software_renderer;
 
 use std::{
     sync::{
@@ -16,50 +20,56 @@ use glutin::{
     dpi::PhysicalSize,
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::{self, Fullscreen, Icon},
-    ContextBuilder, GlProfile, WindowedContext,
+    window::{self, Fullscreen, Icon, WindowBuilder},
+    ContextBuilder, GlProfile,
 };
-use log::trace;
+use log::{error, trace, warn};
 
 #[cfg(target_os = "linux")]
 use glutin::platform::unix::WindowBuilderExtUnix;
 
-use super::{handle_new_grid_size, settings::WindowSettings};
+use super::{handle_new_grid_size, settings::WindowSettings, MonitorInfo, WindowInfo, WINDOW_INFO};
 use crate::{
-    bridge::UiCommand,
+    bridge::{ScreenshotRequest, UiCommand, EXIT_CODE},
     channel_utils::*,
-    cmd_line::CmdLineSettings,
+    cmd_line::{CmdLineSettings, RenderingBackend},
     editor::DrawCommand,
     editor::WindowCommand,
     redraw_scheduler::REDRAW_SCHEDULER,
-    renderer::Renderer,
+    renderer::{FontSettings, Renderer},
     settings::{WindowGeometry, SETTINGS},
 };
 use image::{load_from_memory, GenericImageView, Pixel};
 use keyboard_manager::KeyboardManager;
 use mouse_manager::MouseManager;
 use renderer::SkiaRenderer;
+use screencast::Screencast;
+
+pub use offscreen::start_offscreen_loop;
 
 #[derive(RustEmbed)]
 #[folder = "assets/"]
 struct Asset;
 
 pub struct GlutinWindowWrapper {
-    windowed_context: WindowedContext<glutin::PossiblyCurrent>,
     skia_renderer: SkiaRenderer,
     renderer: Renderer,
     keyboard_manager: KeyboardManager,
     mouse_manager: MouseManager,
     title: String,
     fullscreen: bool,
+    transparency: f32,
+    font_settings: FontSettings,
     saved_inner_size: PhysicalSize<u32>,
+    pending_screenshot: Option<ScreenshotRequest>,
+    screencast: Option<Screencast>,
     ui_command_sender: LoggingTx<UiCommand>,
     window_command_receiver: Receiver<WindowCommand>,
 }
 
 impl GlutinWindowWrapper {
     pub fn toggle_fullscreen(&mut self) {
-        let window = self.windowed_context.window();
+        let window = self.skia_renderer.window();
         if self.fullscreen {
             window.set_fullscreen(None);
         } else {
@@ -71,11 +81,33 @@ impl GlutinWindowWrapper {
     }
 
     pub fn synchronize_settings(&mut self) {
-        let fullscreen = { SETTINGS.get::<WindowSettings>().fullscreen };
+        let WindowSettings {
+            fullscreen,
+            transparency,
+            ..
+        } = SETTINGS.get::<WindowSettings>();
 
         if self.fullscreen != fullscreen {
             self.toggle_fullscreen();
         }
+
+        let font_settings = SETTINGS.get::<FontSettings>();
+        let font_settings_changed = self.font_settings != font_settings;
+        if font_settings_changed {
+            self.font_settings = font_settings.clone();
+            if self.renderer.handle_font_settings_update(font_settings) {
+                let size = self.skia_renderer.window().inner_size();
+                handle_new_grid_size(size, &self.renderer, &self.ui_command_sender);
+                self.update_window_info();
+            }
+        }
+
+        // Cells keep the background, spacing and glyphs they were drawn with, so neovim has to send
+        // all of them again
+        if (self.transparency - transparency).abs() > f32::EPSILON || font_settings_changed {
+            self.transparency = transparency;
+            self.ui_command_sender.send(UiCommand::Redraw).ok();
+        }
     }
 
     #[allow(clippy::needless_collect)]
@@ -87,17 +119,66 @@ impl GlutinWindowWrapper {
                 WindowCommand::SetMouseEnabled(mouse_enabled) => {
                     self.mouse_manager.enabled = mouse_enabled
                 }
+                WindowCommand::FocusWindow => self.skia_renderer.window().focus_window(),
+                WindowCommand::Screenshot(request) => {
+                    self.pending_screenshot = Some(request);
+                    REDRAW_SCHEDULER.queue_next_frame();
+                }
+                WindowCommand::StartScreencast(request) => {
+                    if let Some(screencast) = self.screencast.take() {
+                        screencast.stop();
+                    }
+                    self.screencast = Some(Screencast::start(request));
+                }
+                WindowCommand::StopScreencast => {
+                    if let Some(screencast) = self.screencast.take() {
+                        screencast.stop();
+                    }
+                }
             }
         }
     }
 
+    // Waits for the recording to be written out, so that quitting doesn't cut it short
+    pub fn finish_screencast(&mut self) {
+        if let Some(screencast) = self.screencast.take() {
+            screencast.stop().join().ok();
+        }
+    }
+
+    pub fn update_window_info(&self) {
+        let window = self.skia_renderer.window();
+        let size = window.inner_size();
+        let monitors = window
+            .available_monitors()
+            .map(|monitor| {
+                let position = monitor.position();
+                let size = monitor.size();
+                MonitorInfo {
+                    name: monitor.name().unwrap_or_default(),
+                    position: (position.x, position.y),
+                    size: (size.width, size.height),
+                    scale_factor: monitor.scale_factor(),
+                }
+            })
+            .collect();
+
+        *WINDOW_INFO.write() = WindowInfo {
+            size: (size.width, size.height),
+            cell_size: (self.renderer.font_width, self.renderer.font_height),
+            scale_factor: window.scale_factor(),
+            monitors,
+        };
+    }
+
     pub fn handle_title_changed(&mut self, new_title: String) {
         self.title = new_title;
-        self.windowed_context.window().set_title(&self.title);
+        self.skia_renderer.window().set_title(&self.title);
     }
 
     pub fn handle_quit(&mut self, running: &Arc<AtomicBool>) {
-        if SETTINGS.get::<CmdLineSettings>().remote_tcp.is_none() {
+        let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
+        if cmd_line_settings.remote_tcp.is_none() && cmd_line_settings.server.is_none() {
             self.ui_command_sender
                 .send(UiCommand::Quit)
                 .expect("Could not send quit command to bridge");
@@ -118,7 +199,7 @@ impl GlutinWindowWrapper {
     pub fn handle_event(&mut self, event: Event<()>, running: &Arc<AtomicBool>) {
         self.keyboard_manager.handle_event(&event);
         self.mouse_manager
-            .handle_event(&event, &self.renderer, &self.windowed_context);
+            .handle_event(&event, &mut self.renderer, self.skia_renderer.window());
         match event {
             Event::LoopDestroyed => {
                 self.handle_quit(running);
@@ -135,16 +216,6 @@ impl GlutinWindowWrapper {
             } => {
                 self.handle_scale_factor_update(scale_factor);
             }
-            Event::WindowEvent {
-                event: WindowEvent::DroppedFile(path),
-                ..
-            } => {
-                self.ui_command_sender
-                    .send(UiCommand::FileDrop(
-                        path.into_os_string().into_string().unwrap(),
-                    ))
-                    .ok();
-            }
             Event::WindowEvent {
                 event: WindowEvent::Focused(focus),
                 ..
@@ -156,12 +227,15 @@ impl GlutinWindowWrapper {
                 }
             }
             Event::WindowEvent { .. } => REDRAW_SCHEDULER.queue_next_frame(),
+            // Windows which were covered need to be drawn again, since nothing keeps the last
+            // frame around for software rendering
+            Event::RedrawRequested(_) => REDRAW_SCHEDULER.queue_next_frame(),
             _ => {}
         }
     }
 
     pub fn draw_frame(&mut self, dt: f32) {
-        let window = self.windowed_context.window();
+        let window = self.skia_renderer.window();
         let current_size = window.inner_size();
         let previous_size = self.saved_inner_size;
 
@@ -169,29 +243,53 @@ impl GlutinWindowWrapper {
             trace!("Updating grid size: {:#?}", current_size);
             self.saved_inner_size = current_size;
             handle_new_grid_size(current_size, &self.renderer, &self.ui_command_sender);
-            self.skia_renderer.resize(&self.windowed_context);
+            if let Err(error) = self.skia_renderer.resize() {
+                error!("Could not resize the window surface: {}", error);
+            }
+            self.update_window_info();
         }
 
         let ui_command_sender = self.ui_command_sender.clone();
-
-        if REDRAW_SCHEDULER.should_draw() || SETTINGS.get::<WindowSettings>().no_idle {
+        let screencast_due = self
+            .screencast
+            .as_ref()
+            .map_or(false, |screencast| screencast.frame_due());
+
+        if REDRAW_SCHEDULER.should_draw()
+            || SETTINGS.get::<WindowSettings>().no_idle
+            || screencast_due
+        {
             let renderer = &mut self.renderer;
 
-            {
+            let grid_size_changed = {
                 let canvas = self.skia_renderer.canvas();
+                renderer.draw_frame(canvas, dt)
+            };
 
-                if renderer.draw_frame(canvas, dt) {
-                    handle_new_grid_size(current_size, renderer, &ui_command_sender);
-                }
+            if grid_size_changed {
+                handle_new_grid_size(current_size, renderer, &ui_command_sender);
+                self.update_window_info();
             }
 
-            self.skia_renderer.gr_context.flush(None);
-            self.windowed_context.swap_buffers().unwrap();
+            self.skia_renderer.flush();
+            if let Some(request) = self.pending_screenshot.take() {
+                let region = request
+                    .window
+                    .map(|window| self.renderer.current_window_region(window));
+                screenshot::save_screenshot(self.skia_renderer.surface(), request, region);
+            }
+            if let Some(screencast) = self.screencast.as_mut().filter(|_| screencast_due) {
+                let renderer = &self.renderer;
+                let region = screencast.region.map(|region| renderer.cell_region(region));
+                screencast.capture(self.skia_renderer.surface(), region);
+            }
+            self.skia_renderer.present();
         }
     }
 
     fn handle_scale_factor_update(&mut self, scale_factor: f64) {
         self.renderer.handle_scale_factor_update(scale_factor);
+        self.update_window_info();
     }
 }
 
@@ -200,7 +298,7 @@ pub fn start_loop(
     window_command_receiver: Receiver<WindowCommand>,
     ui_command_sender: LoggingTx<UiCommand>,
     running: Arc<AtomicBool>,
-) {
+) -> Result<(), String> {
     let icon = {
         let icon_data = Asset::get("neovide.ico").expect("Failed to read icon data");
         let icon = load_from_memory(&icon_data).expect("Failed to parse icon data");
@@ -219,24 +317,28 @@ pub fn start_loop(
         .with_title("Neovide")
         .with_window_icon(Some(icon))
         .with_maximized(SETTINGS.get::<CmdLineSettings>().maximized)
-        .with_decorations(!SETTINGS.get::<CmdLineSettings>().frameless);
+        .with_decorations(!SETTINGS.get::<CmdLineSettings>().frameless)
+        // A see through window needs a different visual, which can't be changed once the window
+        // exists, so it is only asked for when transparency is already set
+        .with_transparent(SETTINGS.get::<WindowSettings>().transparency < 1.0);
 
     #[cfg(target_os = "linux")]
     let winit_window_builder = winit_window_builder.with_app_id("Neovide".to_string());
 
-    let windowed_context = ContextBuilder::new()
-        .with_pixel_format(24, 8)
-        .with_stencil_buffer(8)
-        .with_gl_profile(GlProfile::Core)
-        .with_vsync(false)
-        .with_srgb(false)
-        .build_windowed(winit_window_builder, &event_loop)
-        .unwrap();
-    let windowed_context = unsafe { windowed_context.make_current().unwrap() };
+    let mut skia_renderer = match create_skia_renderer(winit_window_builder, &event_loop) {
+        Ok(skia_renderer) => skia_renderer,
+        Err(error) => {
+            error!(
+                "Could not create the window: {}. Use --offscreen to render without one.",
+                error
+            );
+            return Err(error);
+        }
+    };
 
-    let window = windowed_context.window();
+    let window = skia_renderer.window();
 
-    let scale_factor = windowed_context.window().scale_factor();
+    let scale_factor = window.scale_factor();
     let renderer = Renderer::new(batched_draw_command_receiver, scale_factor);
 
     if !window.is_maximized() {
@@ -247,8 +349,10 @@ pub fn start_loop(
     }
 
     let saved_inner_size = window.inner_size();
-
-    let skia_renderer = SkiaRenderer::new(&windowed_context);
+    if let Err(error) = skia_renderer.resize() {
+        error!("Could not resize the window surface: {}", error);
+        return Err(error);
+    }
 
     log::info!(
         "window created (scale_factor: {}, font_size: {}x{})",
@@ -258,23 +362,28 @@ pub fn start_loop(
     );
 
     let mut window_wrapper = GlutinWindowWrapper {
-        windowed_context,
         skia_renderer,
         renderer,
         keyboard_manager: KeyboardManager::new(ui_command_sender.clone()),
         mouse_manager: MouseManager::new(ui_command_sender.clone()),
         title: String::from("Neovide"),
         fullscreen: false,
+        transparency: 1.0,
+        font_settings: FontSettings::default(),
         saved_inner_size,
+        pending_screenshot: None,
+        screencast: None,
         ui_command_sender,
         window_command_receiver,
     };
+    window_wrapper.update_window_info();
 
     let mut previous_frame_start = Instant::now();
 
     event_loop.run(move |e, _window_target, control_flow| {
         if !running.load(Ordering::Relaxed) {
-            std::process::exit(0);
+            window_wrapper.finish_screencast();
+            std::process::exit(EXIT_CODE.load(Ordering::Relaxed));
         }
 
         let frame_start = Instant::now();
@@ -297,6 +406,45 @@ pub fn start_loop(
     });
 }
 
+fn create_gl_renderer(
+    window_builder: WindowBuilder,
+    event_loop: &EventLoop<()>,
+) -> Result<SkiaRenderer, String> {
+    let windowed_context = ContextBuilder::new()
+        .with_pixel_format(24, 8)
+        .with_stencil_buffer(8)
+        .with_gl_profile(GlProfile::Core)
+        .with_vsync(false)
+        .with_srgb(false)
+        .build_windowed(window_builder, event_loop)
+        .map_err(|error| error.to_string())?;
+    let windowed_context =
+        unsafe { windowed_context.make_current() }.map_err(|(_, error)| error.to_string())?;
+
+    SkiaRenderer::new_gl(windowed_context)
+}
+
+// OpenGL is used whenever it works, unless software rendering was asked for
+fn create_skia_renderer(
+    window_builder: WindowBuilder,
+    event_loop: &EventLoop<()>,
+) -> Result<SkiaRenderer, String> {
+    if SETTINGS.get::<CmdLineSettings>().renderer == RenderingBackend::Gl {
+        match create_gl_renderer(window_builder.clone(), event_loop) {
+            Ok(renderer) => return Ok(renderer),
+            Err(error) => warn!(
+                "Could not create an OpenGL context, falling back to software rendering: {}",
+                error
+            ),
+        }
+    }
+
+    let window = window_builder
+        .build(event_loop)
+        .map_err(|error| error.to_string())?;
+    SkiaRenderer::new_software(window)
+}
+
 fn get_initial_window_size(font_dimesions: (u64, u64)) -> PhysicalSize<u32> {
     let WindowGeometry { width, height } = SETTINGS.get::<CmdLineSettings>().geometry;
     let (font_width, font_height) = font_dimesions;
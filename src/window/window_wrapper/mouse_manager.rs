@@ -1,14 +1,16 @@
+use std::path::PathBuf;
+
 use glutin::{
     self,
     dpi::PhysicalPosition,
     event::{ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent},
-    PossiblyCurrent, WindowedContext,
+    window::Window,
 };
-use skia_safe::Rect;
+use skia_safe::{Point, Rect};
 
 use crate::bridge::UiCommand;
 use crate::channel_utils::LoggingTx;
-use crate::renderer::{Renderer, WindowDrawDetails};
+use crate::renderer::{Renderer, TabLineTarget, WindowDrawDetails};
 use crate::settings::SETTINGS;
 use crate::window::WindowSettings;
 
@@ -41,14 +43,91 @@ fn to_grid_coords(
     )
 }
 
+// The rendered window regions are sorted by draw order, so the earlier windows in the list are
+// drawn under the later ones
+fn window_details_at(
+    renderer: &Renderer,
+    position: PhysicalPosition<f32>,
+) -> Option<&WindowDrawDetails> {
+    renderer
+        .window_regions
+        .iter()
+        .filter(|details| {
+            position.x >= details.region.left
+                && position.x < details.region.right
+                && position.y >= details.region.top
+                && position.y < details.region.bottom
+        })
+        .last()
+}
+
+// Winit doesn't report pointer motion while something is dragged over the window, so drops ask the
+// windowing system where the pointer is
+#[cfg(target_os = "linux")]
+fn cursor_position(window: &Window) -> Option<PhysicalPosition<i32>> {
+    use glutin::platform::unix::WindowExtUnix;
+    use x11_dl::xlib::Xlib;
+
+    let display = window.xlib_display()? as *mut x11_dl::xlib::Display;
+    let window = window.xlib_window()?;
+    let xlib = Xlib::open().ok()?;
+    let (mut root, mut child) = (0, 0);
+    let (mut root_x, mut root_y, mut x, mut y) = (0, 0, 0, 0);
+    let mut mask = 0;
+    let on_screen = unsafe {
+        (xlib.XQueryPointer)(
+            display,
+            window,
+            &mut root,
+            &mut child,
+            &mut root_x,
+            &mut root_y,
+            &mut x,
+            &mut y,
+            &mut mask,
+        )
+    };
+    if on_screen == 0 {
+        return None;
+    }
+    Some(PhysicalPosition::new(x, y))
+}
+
+#[cfg(windows)]
+fn cursor_position(window: &Window) -> Option<PhysicalPosition<i32>> {
+    use glutin::platform::windows::WindowExtWindows;
+    use winapi::shared::windef::POINT;
+    use winapi::um::winuser::{GetCursorPos, ScreenToClient};
+
+    let mut point = POINT { x: 0, y: 0 };
+    unsafe {
+        if GetCursorPos(&mut point) == 0 || ScreenToClient(window.hwnd() as _, &mut point) == 0 {
+            return None;
+        }
+    }
+    Some(PhysicalPosition::new(point.x, point.y))
+}
+
+// Elsewhere drops land where the pointer was last seen
+#[cfg(not(any(target_os = "linux", windows)))]
+fn cursor_position(_window: &Window) -> Option<PhysicalPosition<i32>> {
+    None
+}
+
 pub struct MouseManager {
     command_sender: LoggingTx<UiCommand>,
     dragging: bool,
     has_moved: bool,
     position: PhysicalPosition<u32>,
+    pixel_position: PhysicalPosition<f32>,
     relative_position: PhysicalPosition<u32>,
     drag_position: PhysicalPosition<u32>,
     window_details_under_mouse: Option<WindowDrawDetails>,
+    hovered_popup_menu_item: Option<u64>,
+    ignore_release: bool,
+    // Dropping several files sends one event per file, so they are collected until the end of the
+    // frame and opened together
+    dropped_files: Vec<PathBuf>,
     pub enabled: bool,
 }
 
@@ -59,26 +138,48 @@ impl MouseManager {
             dragging: false,
             has_moved: false,
             position: PhysicalPosition::new(0, 0),
+            pixel_position: PhysicalPosition::new(0.0, 0.0),
             relative_position: PhysicalPosition::new(0, 0),
             drag_position: PhysicalPosition::new(0, 0),
             window_details_under_mouse: None,
+            hovered_popup_menu_item: None,
+            ignore_release: false,
+            dropped_files: Vec::new(),
             enabled: true,
         }
     }
 
-    fn handle_pointer_motion(
-        &mut self,
-        x: i32,
-        y: i32,
-        renderer: &Renderer,
-        windowed_context: &WindowedContext<PossiblyCurrent>,
-    ) {
-        let size = windowed_context.window().inner_size();
+    fn handle_pointer_motion(&mut self, x: i32, y: i32, renderer: &Renderer, window: &Window) {
+        let size = window.inner_size();
         if x < 0 || x as u32 >= size.width || y < 0 || y as u32 >= size.height {
             return;
         }
 
-        let position: PhysicalPosition<f32> = PhysicalPosition::new(x as f32, y as f32);
+        // The grids are drawn below the tab line, so positions are relative to where they start
+        let tab_line_height = renderer.tab_line_height() as f32;
+        let position: PhysicalPosition<f32> =
+            PhysicalPosition::new(x as f32, y as f32 - tab_line_height);
+        self.pixel_position = position;
+
+        if position.y < 0.0 && !self.dragging {
+            return;
+        }
+
+        // Hovering over the popup menu previews the item without inserting it, like moving
+        // through the menu with <C-n>
+        let hovered_item = renderer.popup_menu_item_at(Point::new(position.x, position.y));
+        if hovered_item != self.hovered_popup_menu_item {
+            if let Some(item) = hovered_item {
+                self.command_sender
+                    .send(UiCommand::SelectPopupMenuItem {
+                        item,
+                        insert: false,
+                        finish: false,
+                    })
+                    .ok();
+            }
+            self.hovered_popup_menu_item = hovered_item;
+        }
 
         // If dragging, the relevant window (the one which we send all commands to) is the one
         // which the mouse drag started on. Otherwise its the top rendered window
@@ -92,18 +193,7 @@ impl MouseManager {
                         .id
             })
         } else {
-            // the rendered window regions are sorted by draw order, so the earlier windows in the
-            // list are drawn under the later ones
-            renderer
-                .window_regions
-                .iter()
-                .filter(|details| {
-                    position.x >= details.region.left
-                        && position.x < details.region.right
-                        && position.y >= details.region.top
-                        && position.y < details.region.bottom
-                })
-                .last()
+            window_details_at(renderer, position)
         };
 
         let global_bounds = relevant_window_details
@@ -157,7 +247,43 @@ impl MouseManager {
         }
     }
 
-    fn handle_pointer_transition(&mut self, down: bool) {
+    fn handle_pointer_transition(&mut self, down: bool, renderer: &Renderer) {
+        // The tab line and popup menu are drawn by neovide, so clicks on them are never sent to
+        // neovim. The matching release is swallowed as well.
+        let pixel_position = Point::new(self.pixel_position.x, self.pixel_position.y);
+        if down {
+            let tab_line_position =
+                pixel_position + Point::new(0.0, renderer.tab_line_height() as f32);
+            let ui_command = if let Some(target) = renderer.tab_line_target_at(tab_line_position) {
+                Some(match target {
+                    TabLineTarget::Tab(tab) => UiCommand::SwitchTab(tab),
+                    TabLineTarget::CloseButton(tab) => UiCommand::CloseTab(tab),
+                })
+            } else {
+                renderer.popup_menu_item_at(pixel_position).map(|item| {
+                    UiCommand::SelectPopupMenuItem {
+                        item,
+                        insert: true,
+                        finish: true,
+                    }
+                })
+            };
+
+            if let Some(ui_command) = ui_command {
+                self.command_sender.send(ui_command).ok();
+                self.ignore_release = true;
+                return;
+            }
+
+            // Empty space in the tab line
+            if pixel_position.y < 0.0 {
+                self.ignore_release = true;
+                return;
+            }
+        } else if std::mem::take(&mut self.ignore_release) {
+            return;
+        }
+
         // For some reason pointer down is handled differently from pointer up and drag.
         // Floating windows: relative coordinates are great.
         // Non floating windows: rather than global coordinates, relative are needed
@@ -192,12 +318,18 @@ impl MouseManager {
         }
     }
 
-    fn handle_mouse_wheel(&mut self, x: f32, y: f32) {
-        if !self.enabled {
+    fn handle_mouse_wheel(&mut self, x: f32, y: f32, renderer: &mut Renderer) {
+        let scroll_dead_zone = SETTINGS.get::<WindowSettings>().scroll_dead_zone;
+
+        // The message history is drawn by neovide, so neovim never sees scrolls over it
+        let pixel_position = Point::new(self.pixel_position.x, self.pixel_position.y);
+        if y.abs() > scroll_dead_zone && renderer.scroll_message_history(pixel_position, y > 0.0) {
             return;
         }
 
-        let scroll_dead_zone = SETTINGS.get::<WindowSettings>().scroll_dead_zone;
+        if !self.enabled {
+            return;
+        }
 
         let vertical_input_type = match y {
             _ if y > scroll_dead_zone => Some("up"),
@@ -240,22 +372,48 @@ impl MouseManager {
         }
     }
 
-    pub fn handle_event(
-        &mut self,
-        event: &Event<()>,
-        renderer: &Renderer,
-        windowed_context: &WindowedContext<PossiblyCurrent>,
-    ) {
+    fn handle_drop(&mut self, renderer: &Renderer, window: &Window) {
+        // Winit only reports drops as paths. Text dragged from other applications shows up as a
+        // path that doesn't exist on some platforms, and can't be told apart from a missing
+        // file, so it is left out.
+        let dropped_files: Vec<_> = std::mem::take(&mut self.dropped_files)
+            .into_iter()
+            .filter(|path| path.exists())
+            .collect();
+        if dropped_files.is_empty() {
+            return;
+        }
+
+        let position = cursor_position(window)
+            .map(|position| {
+                PhysicalPosition::new(
+                    position.x as f32,
+                    position.y as f32 - renderer.tab_line_height() as f32,
+                )
+            })
+            .unwrap_or(self.pixel_position);
+
+        self.command_sender
+            .send(UiCommand::FileDrop {
+                files: dropped_files
+                    .into_iter()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .collect(),
+                mode: SETTINGS.get::<WindowSettings>().file_drop_action,
+                window: window_details_at(renderer, position)
+                    .and_then(|details| details.window_handle),
+                position: to_grid_coords(position, renderer.font_width, renderer.font_height)
+                    .into(),
+            })
+            .ok();
+    }
+
+    pub fn handle_event(&mut self, event: &Event<()>, renderer: &mut Renderer, window: &Window) {
         match event {
             Event::WindowEvent {
                 event: WindowEvent::CursorMoved { position, .. },
                 ..
-            } => self.handle_pointer_motion(
-                position.x as i32,
-                position.y as i32,
-                renderer,
-                windowed_context,
-            ),
+            } => self.handle_pointer_motion(position.x as i32, position.y as i32, renderer, window),
             Event::WindowEvent {
                 event:
                     WindowEvent::MouseWheel {
@@ -263,7 +421,7 @@ impl MouseManager {
                         ..
                     },
                 ..
-            } => self.handle_mouse_wheel(*x as f32, *y as f32),
+            } => self.handle_mouse_wheel(*x as f32, *y as f32, renderer),
             Event::WindowEvent {
                 event:
                     WindowEvent::MouseWheel {
@@ -271,7 +429,7 @@ impl MouseManager {
                         ..
                     },
                 ..
-            } => self.handle_mouse_wheel(position.x as f32, position.y as f32),
+            } => self.handle_mouse_wheel(position.x as f32, position.y as f32, renderer),
             Event::WindowEvent {
                 event:
                     WindowEvent::MouseInput {
@@ -280,7 +438,12 @@ impl MouseManager {
                         ..
                     },
                 ..
-            } => self.handle_pointer_transition(state == &ElementState::Pressed),
+            } => self.handle_pointer_transition(state == &ElementState::Pressed, renderer),
+            Event::WindowEvent {
+                event: WindowEvent::DroppedFile(path),
+                ..
+            } => self.dropped_files.push(path.clone()),
+            Event::MainEventsCleared => self.handle_drop(renderer, window),
             _ => {}
         }
     }
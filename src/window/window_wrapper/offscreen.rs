@@ -0,0 +1,133 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use glutin::dpi::PhysicalSize;
+use log::info;
+
+use super::{
+    get_initial_window_size, screencast::Screencast, screenshot,
+    software_renderer::create_raster_surface,
+};
+use crate::{
+    bridge::{ScreenshotRequest, UiCommand, EXIT_CODE},
+    channel_utils::*,
+    editor::{DrawCommand, WindowCommand},
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::{FontSettings, Renderer},
+    settings::SETTINGS,
+    window::{handle_new_grid_size, WindowInfo, WindowSettings, WINDOW_INFO},
+};
+
+// Renders frames on the cpu without ever opening a window, for machines without a display such as
+// CI. The frames can only be seen through screenshots.
+pub fn start_offscreen_loop(
+    batched_draw_command_receiver: Receiver<Vec<DrawCommand>>,
+    window_command_receiver: Receiver<WindowCommand>,
+    ui_command_sender: LoggingTx<UiCommand>,
+    running: Arc<AtomicBool>,
+) {
+    let mut renderer = Renderer::new(batched_draw_command_receiver, 1.0);
+    let size: PhysicalSize<u32> =
+        get_initial_window_size((renderer.font_width, renderer.font_height));
+    let mut surface = create_raster_surface((size.width, size.height));
+    handle_new_grid_size(size, &renderer, &ui_command_sender);
+
+    let update_window_info = |renderer: &Renderer| {
+        *WINDOW_INFO.write() = WindowInfo {
+            size: (size.width, size.height),
+            cell_size: (renderer.font_width, renderer.font_height),
+            scale_factor: 1.0,
+            monitors: Vec::new(),
+        };
+    };
+    update_window_info(&renderer);
+    info!(
+        "offscreen surface created (size: {}x{}, font_size: {}x{})",
+        size.width, size.height, renderer.font_width, renderer.font_height,
+    );
+
+    let mut pending_screenshot: Option<ScreenshotRequest> = None;
+    let mut screencast: Option<Screencast> = None;
+    let mut font_settings = FontSettings::default();
+    let mut previous_frame_start = Instant::now();
+
+    while running.load(Ordering::Relaxed) {
+        let frame_start = Instant::now();
+
+        for window_command in window_command_receiver.try_iter() {
+            match window_command {
+                WindowCommand::Screenshot(request) => {
+                    pending_screenshot = Some(request);
+                    REDRAW_SCHEDULER.queue_next_frame();
+                }
+                WindowCommand::StartScreencast(request) => {
+                    if let Some(screencast) = screencast.take() {
+                        screencast.stop();
+                    }
+                    screencast = Some(Screencast::start(request));
+                }
+                WindowCommand::StopScreencast => {
+                    if let Some(screencast) = screencast.take() {
+                        screencast.stop();
+                    }
+                }
+                // There is no window to change
+                WindowCommand::TitleChanged(_)
+                | WindowCommand::SetMouseEnabled(_)
+                | WindowCommand::FocusWindow => {}
+            }
+        }
+
+        let new_font_settings = SETTINGS.get::<FontSettings>();
+        if font_settings != new_font_settings {
+            font_settings = new_font_settings.clone();
+            if renderer.handle_font_settings_update(new_font_settings) {
+                handle_new_grid_size(size, &renderer, &ui_command_sender);
+                update_window_info(&renderer);
+            }
+            ui_command_sender.send(UiCommand::Redraw).ok();
+        }
+
+        let screencast_due = screencast
+            .as_ref()
+            .map_or(false, |screencast| screencast.frame_due());
+        if REDRAW_SCHEDULER.should_draw()
+            || SETTINGS.get::<WindowSettings>().no_idle
+            || screencast_due
+        {
+            let dt = (frame_start - previous_frame_start).as_secs_f32();
+            if renderer.draw_frame(surface.canvas(), dt) {
+                handle_new_grid_size(size, &renderer, &ui_command_sender);
+                update_window_info(&renderer);
+            }
+
+            if let Some(request) = pending_screenshot.take() {
+                let region = request
+                    .window
+                    .map(|window| renderer.current_window_region(window));
+                screenshot::save_screenshot(&mut surface, request, region);
+            }
+            if let Some(screencast) = screencast.as_mut().filter(|_| screencast_due) {
+                let region = screencast.region.map(|region| renderer.cell_region(region));
+                screencast.capture(&mut surface, region);
+            }
+        }
+        previous_frame_start = frame_start;
+
+        let refresh_rate = SETTINGS.get::<WindowSettings>().refresh_rate as f32;
+        let frame_duration = Duration::from_secs_f32(1.0 / refresh_rate);
+        thread::sleep(frame_duration.saturating_sub(frame_start.elapsed()));
+    }
+
+    if let Some(screencast) = screencast {
+        screencast.stop().join().ok();
+    }
+    std::process::exit(EXIT_CODE.load(Ordering::Relaxed));
+}
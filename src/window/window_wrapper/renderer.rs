@@ -1,18 +1,26 @@
+use glutin::window::Window;
+use image::RgbaImage;
 use skia_safe::gpu::gl::FramebufferInfo;
 use skia_safe::gpu::{BackendRenderTarget, DirectContext, SurfaceOrigin};
-use skia_safe::{Canvas, ColorType, Surface};
+use skia_safe::{AlphaType, Canvas, ColorType, ImageInfo, Surface};
 use std::convert::TryInto;
 
 use gl::types::*;
+use log::warn;
+
+use super::software_renderer::SoftwareRenderer;
+
 type WindowedContext = glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>;
+type RawContext = glutin::RawContext<glutin::PossiblyCurrent>;
 
 fn create_surface(
-    windowed_context: &WindowedContext,
+    context: &RawContext,
+    window: &Window,
     gr_context: &mut DirectContext,
     fb_info: FramebufferInfo,
-) -> Surface {
-    let pixel_format = windowed_context.get_pixel_format();
-    let size = windowed_context.window().inner_size();
+) -> Result<Surface, String> {
+    let pixel_format = context.get_pixel_format();
+    let size = window.inner_size();
     let size = (
         size.width.try_into().expect("Could not convert width"),
         size.height.try_into().expect("Could not convert height"),
@@ -28,7 +36,7 @@ fn create_surface(
             .expect("Could not convert stencil"),
         fb_info,
     );
-    windowed_context.resize(size.into());
+    context.resize(window.inner_size());
     Surface::from_backend_render_target(
         gr_context,
         &backend_render_target,
@@ -37,52 +45,155 @@ fn create_surface(
         None,
         None,
     )
-    .expect("Could not create skia surface")
+    .ok_or_else(|| "Could not create skia surface".to_owned())
 }
 
-pub struct SkiaRenderer {
-    pub gr_context: DirectContext,
+// Reads back what has been drawn to a surface so far
+pub fn read_pixels(surface: &mut Surface) -> Option<RgbaImage> {
+    let (width, height) = (surface.width(), surface.height());
+    let image_info = ImageInfo::new(
+        (width, height),
+        ColorType::RGBA8888,
+        AlphaType::Unpremul,
+        None,
+    );
+    let row_bytes = width as usize * 4;
+    let mut pixels = vec![0; row_bytes * height as usize];
+
+    if !surface.read_pixels(&image_info, &mut pixels, row_bytes, (0, 0)) {
+        return None;
+    }
+
+    RgbaImage::from_raw(width as u32, height as u32, pixels)
+}
+
+struct GlRenderer {
+    context: RawContext,
+    gr_context: DirectContext,
     fb_info: FramebufferInfo,
     surface: Surface,
 }
 
-impl SkiaRenderer {
-    pub fn new(windowed_context: &WindowedContext) -> SkiaRenderer {
-        gl::load_with(|s| windowed_context.get_proc_address(s));
+impl GlRenderer {
+    // Fails when the context doesn't support what skia needs, such as over X forwarding
+    fn new(context: RawContext, window: &Window) -> Result<GlRenderer, String> {
+        gl::load_with(|s| context.get_proc_address(s));
 
         let interface = skia_safe::gpu::gl::Interface::new_load_with(|name| {
             if name == "eglGetCurrentDisplay" {
                 return std::ptr::null();
             }
-            windowed_context.get_proc_address(name)
+            context.get_proc_address(name)
         })
-        .expect("Could not create interface");
+        .ok_or("Could not create interface")?;
 
         let mut gr_context = skia_safe::gpu::DirectContext::new_gl(Some(interface), None)
-            .expect("Could not create direct context");
+            .ok_or("Could not create direct context")?;
         let fb_info = {
             let mut fboid: GLint = 0;
             unsafe { gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut fboid) };
 
             FramebufferInfo {
-                fboid: fboid.try_into().expect("Could not create frame buffer id"),
+                fboid: fboid
+                    .try_into()
+                    .map_err(|_| format!("Invalid frame buffer id {}", fboid))?,
                 format: skia_safe::gpu::gl::Format::RGBA8.into(),
             }
         };
-        let surface = create_surface(windowed_context, &mut gr_context, fb_info);
+        let surface = create_surface(&context, window, &mut gr_context, fb_info)?;
 
-        SkiaRenderer {
+        Ok(GlRenderer {
+            context,
             gr_context,
             fb_info,
             surface,
+        })
+    }
+
+    fn resize(&mut self, window: &Window) -> Result<(), String> {
+        self.surface = create_surface(&self.context, window, &mut self.gr_context, self.fb_info)?;
+        Ok(())
+    }
+}
+
+enum Backend {
+    Gl(GlRenderer),
+    Software(SoftwareRenderer),
+}
+
+// Draws either through OpenGL, or on the cpu when there is no usable OpenGL context
+pub struct SkiaRenderer {
+    // Declared before the window so that the OpenGL context is dropped first
+    backend: Backend,
+    window: Window,
+}
+
+impl SkiaRenderer {
+    pub fn new_gl(windowed_context: WindowedContext) -> Result<SkiaRenderer, String> {
+        // The window has to outlive the context, which the field order of SkiaRenderer ensures
+        let (context, window) = unsafe { windowed_context.split() };
+        let renderer = GlRenderer::new(context, &window)?;
+        Ok(SkiaRenderer {
+            backend: Backend::Gl(renderer),
+            window,
+        })
+    }
+
+    pub fn new_software(window: Window) -> Result<SkiaRenderer, String> {
+        let renderer = SoftwareRenderer::new(&window)?;
+        Ok(SkiaRenderer {
+            backend: Backend::Software(renderer),
+            window,
+        })
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn surface(&mut self) -> &mut Surface {
+        match &mut self.backend {
+            Backend::Gl(renderer) => &mut renderer.surface,
+            Backend::Software(renderer) => renderer.surface(),
         }
     }
 
     pub fn canvas(&mut self) -> &mut Canvas {
-        self.surface.canvas()
+        self.surface().canvas()
+    }
+
+    // Submits the drawing commands of the frame, after which the surface can be read
+    pub fn flush(&mut self) {
+        if let Backend::Gl(renderer) = &mut self.backend {
+            renderer.gr_context.flush(None);
+        }
     }
 
-    pub fn resize(&mut self, windowed_context: &WindowedContext) {
-        self.surface = create_surface(windowed_context, &mut self.gr_context, self.fb_info);
+    pub fn present(&mut self) {
+        match &mut self.backend {
+            Backend::Gl(renderer) => renderer.context.swap_buffers().unwrap(),
+            Backend::Software(renderer) => renderer.present(),
+        }
+    }
+
+    // Switches to software rendering when OpenGL can't provide a surface of the new size. The
+    // previous surface is kept if that fails too.
+    pub fn resize(&mut self) -> Result<(), String> {
+        let error = match &mut self.backend {
+            Backend::Gl(renderer) => match renderer.resize(&self.window) {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            },
+            Backend::Software(renderer) => {
+                renderer.resize(&self.window);
+                return Ok(());
+            }
+        };
+        warn!(
+            "Could not resize the OpenGL surface, falling back to software rendering: {}",
+            error
+        );
+        self.backend = Backend::Software(SoftwareRenderer::new(&self.window)?);
+        Ok(())
     }
 }
@@ -0,0 +1,281 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use gif::SetParameter;
+use image::{imageops, RgbaImage};
+use log::{error, info};
+use skia_safe::{Rect, Surface};
+
+use super::renderer::read_pixels;
+use super::screenshot::{output_path, prepare_image};
+use crate::bridge::ScreencastRequest;
+
+// Gif delays are counted in hundredths of a second, and most viewers slow down anything shorter
+// than two of them
+const MAX_FRAME_RATE: u32 = 50;
+
+enum EncoderMessage {
+    // A frame and when it was captured, counted from the start of the recording
+    Frame(RgbaImage, Duration),
+    Stop(Duration),
+}
+
+enum Encoder {
+    // Gifs are written while recording
+    Gif(Option<gif::Encoder<BufWriter<File>>>),
+    // Animated pngs declare their frame count up front, so the frames are spooled to a file next
+    // to the recording until the end, and only their lengths are kept
+    Apng {
+        frames: Option<BufWriter<File>>,
+        size: (u32, u32),
+        lengths: Vec<Duration>,
+    },
+}
+
+fn hundredths(time: Duration) -> u64 {
+    (time.as_secs_f64() * 100.0).round() as u64
+}
+
+fn spool_path(path: &Path) -> PathBuf {
+    let mut spool_path = OsString::from(path);
+    spool_path.push(".frames");
+    PathBuf::from(spool_path)
+}
+
+// Every frame has the size of the first one, so that resizing the window while recording doesn't
+// break the file. Frames of another size are cut short or padded with transparent pixels.
+fn fit_frame(image: RgbaImage, (width, height): (u32, u32)) -> RgbaImage {
+    if image.dimensions() == (width, height) {
+        return image;
+    }
+
+    let mut fitted = RgbaImage::new(width, height);
+    imageops::replace(&mut fitted, &image, 0, 0);
+    fitted
+}
+
+impl Encoder {
+    fn for_path(path: &Path) -> Encoder {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("png") | Some("apng") => Encoder::Apng {
+                frames: None,
+                size: (0, 0),
+                lengths: Vec::new(),
+            },
+            _ => Encoder::Gif(None),
+        }
+    }
+
+    // Frames are only written once the next one arrives, since that decides how long they show
+    fn write_frame(
+        &mut self,
+        path: &Path,
+        image: RgbaImage,
+        (start, end): (Duration, Duration),
+    ) -> io::Result<()> {
+        match self {
+            Encoder::Gif(encoder) => {
+                let (width, height) = (image.width() as u16, image.height() as u16);
+                if encoder.is_none() {
+                    let mut new_encoder =
+                        gif::Encoder::new(BufWriter::new(File::create(path)?), width, height, &[])?;
+                    new_encoder.set(gif::Repeat::Infinite)?;
+                    *encoder = Some(new_encoder);
+                }
+
+                let mut pixels = image.into_raw();
+                let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+                // Rounding the ends instead of the lengths keeps long recordings from drifting
+                frame.delay = (hundredths(end) - hundredths(start)).max(2) as u16;
+                encoder.as_mut().unwrap().write_frame(&frame)
+            }
+            Encoder::Apng {
+                frames,
+                size,
+                lengths,
+            } => {
+                if frames.is_none() {
+                    *frames = Some(BufWriter::new(File::create(spool_path(path))?));
+                    *size = image.dimensions();
+                }
+
+                frames.as_mut().unwrap().write_all(&image)?;
+                lengths.push(end - start);
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self, path: &Path) -> io::Result<()> {
+        let (frames, (width, height), lengths) = match self {
+            Encoder::Apng {
+                frames: Some(frames),
+                size,
+                lengths,
+            } => (frames, size, lengths),
+            _ => return Ok(()),
+        };
+        drop(frames.into_inner()?);
+
+        let spool_path = spool_path(path);
+        let mut frames = BufReader::new(File::open(&spool_path)?);
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(lengths.len() as u32, 0)?;
+        let mut writer = encoder.write_header()?;
+        let mut image = vec![0; width as usize * height as usize * 4];
+        for length in lengths {
+            frames.read_exact(&mut image)?;
+            writer.set_frame_delay(length.as_millis().min(u16::MAX as u128) as u16, 1000)?;
+            writer.write_image_data(&image)?;
+        }
+        writer.finish()?;
+
+        fs::remove_file(spool_path)
+    }
+}
+
+// Encodes on its own thread so that recording doesn't slow down drawing. Frames which look like
+// the one before are merged into it, which keeps recordings of a mostly still editor small.
+fn encode(path: PathBuf, receiver: Receiver<EncoderMessage>) -> io::Result<()> {
+    let mut encoder = Encoder::for_path(&path);
+    let mut previous: Option<(RgbaImage, Duration)> = None;
+    let mut size = None;
+
+    for message in receiver {
+        let (image, time) = match message {
+            EncoderMessage::Frame(image, time) => {
+                let size = *size.get_or_insert(image.dimensions());
+                (Some(fit_frame(image, size)), time)
+            }
+            EncoderMessage::Stop(time) => (None, time),
+        };
+
+        let unchanged = match (&previous, &image) {
+            (Some((previous_image, _)), Some(image)) => previous_image == image,
+            _ => false,
+        };
+        if unchanged {
+            continue;
+        }
+
+        if let Some((previous_image, start)) = previous.take() {
+            encoder.write_frame(&path, previous_image, (start, time))?;
+        }
+        match image {
+            Some(image) => previous = Some((image, time)),
+            None => break,
+        }
+    }
+
+    encoder.finish(&path)
+}
+
+// A recording of the frames drawn to the window, taken at a fixed rate
+pub struct Screencast {
+    start: Instant,
+    interval: Duration,
+    captured_frames: u32,
+    // The grid cells to record as (column, row, width, height), when not recording everything
+    pub region: Option<(u64, u64, u64, u64)>,
+    sender: Sender<EncoderMessage>,
+    encoder: JoinHandle<()>,
+}
+
+impl Screencast {
+    pub fn start(request: ScreencastRequest) -> Screencast {
+        let path = output_path(request.path, "gif");
+        let (sender, receiver) = channel();
+        let encoder = thread::spawn(move || match encode(path.clone(), receiver) {
+            Ok(()) => info!("Saved recording to {}", path.display()),
+            Err(error) => error!("Could not save recording to {}: {}", path.display(), error),
+        });
+        let frame_rate = request.frame_rate.max(1).min(MAX_FRAME_RATE);
+
+        Screencast {
+            start: Instant::now(),
+            interval: Duration::from_secs(1) / frame_rate,
+            captured_frames: 0,
+            region: request.region,
+            sender,
+            encoder,
+        }
+    }
+
+    // Frames are due at a fixed rate, whether or not anything changed on screen
+    pub fn frame_due(&self) -> bool {
+        self.start.elapsed() >= self.interval * self.captured_frames
+    }
+
+    pub fn capture(&mut self, surface: &mut Surface, region: Option<Rect>) {
+        let time = self.start.elapsed();
+        if let Some(image) = read_pixels(surface) {
+            let image = prepare_image(image, region, 1.0);
+            self.sender.send(EncoderMessage::Frame(image, time)).ok();
+        }
+
+        // Frames missed while drawing was slow are skipped rather than caught up on
+        self.captured_frames = (time.as_secs_f64() / self.interval.as_secs_f64()) as u32 + 1;
+    }
+
+    // Returns the encoder, which keeps writing in the background until the file is complete
+    pub fn stop(self) -> JoinHandle<()> {
+        self.sender
+            .send(EncoderMessage::Stop(self.start.elapsed()))
+            .ok();
+        self.encoder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_frames_extend_the_previous_one() {
+        let path = std::env::temp_dir().join(format!("neovide-test-{}.png", std::process::id()));
+        let (sender, receiver) = channel();
+        let still = RgbaImage::new(4, 2);
+        let moved = RgbaImage::from_pixel(4, 2, image::Rgba([255, 0, 0, 255]));
+
+        for (image, millis) in &[(&still, 0), (&still, 100), (&moved, 200), (&moved, 300)] {
+            sender
+                .send(EncoderMessage::Frame(
+                    (*image).clone(),
+                    Duration::from_millis(*millis),
+                ))
+                .unwrap();
+        }
+        sender
+            .send(EncoderMessage::Stop(Duration::from_millis(500)))
+            .unwrap();
+        encode(path.clone(), receiver).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let animation = reader.info().animation_control.unwrap();
+        assert_eq!(animation.num_frames, 2);
+        assert!(!spool_path(&path).exists());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn frames_keep_the_size_of_the_first_one() {
+        let red = image::Rgba([255, 0, 0, 255]);
+
+        let smaller = fit_frame(RgbaImage::from_pixel(2, 2, red), (4, 3));
+        assert_eq!(smaller.dimensions(), (4, 3));
+        assert_eq!(*smaller.get_pixel(1, 1), red);
+        assert_eq!(*smaller.get_pixel(3, 2), image::Rgba([0, 0, 0, 0]));
+
+        let larger = fit_frame(RgbaImage::from_pixel(8, 8, red), (4, 3));
+        assert_eq!(larger.dimensions(), (4, 3));
+        assert_eq!(*larger.get_pixel(3, 2), red);
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::{imageops, FilterType, RgbaImage};
+use log::{error, info};
+use skia_safe::{Rect, Surface};
+
+use super::renderer::read_pixels;
+use crate::bridge::ScreenshotRequest;
+
+// Screenshots and recordings requested with a directory, or with the empty path, get a
+// timestamped name
+pub fn output_path(mut path: PathBuf, extension: &str) -> PathBuf {
+    if path.as_os_str().is_empty() || path.is_dir() {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        path.push(format!("neovide-{}", timestamp));
+    }
+
+    if path.extension().is_none() {
+        path.set_extension(extension);
+    }
+
+    path
+}
+
+// Cuts the frame down to the region and scales what is left
+pub fn prepare_image(mut image: RgbaImage, region: Option<Rect>, scale: f32) -> RgbaImage {
+    if let Some(region) = region {
+        let region = region.round();
+        let (left, top) = (region.left.max(0) as u32, region.top.max(0) as u32);
+        let (right, bottom) = (region.right.max(0) as u32, region.bottom.max(0) as u32);
+        let cropped = imageops::crop(
+            &mut image,
+            left,
+            top,
+            right.saturating_sub(left),
+            bottom.saturating_sub(top),
+        )
+        .to_image();
+        image = cropped;
+    }
+
+    if (scale - 1.0).abs() > f32::EPSILON {
+        let width = ((image.width() as f32 * scale).round() as u32).max(1);
+        let height = ((image.height() as f32 * scale).round() as u32).max(1);
+        image = imageops::resize(&image, width, height, FilterType::Triangle);
+    }
+
+    image
+}
+
+// Saves the frame that was just drawn. Has to be called before the buffers are swapped. Only the
+// pixels are read here, the image is cropped, encoded and written on its own thread so that
+// drawing isn't held up.
+pub fn save_screenshot(surface: &mut Surface, request: ScreenshotRequest, region: Option<Rect>) {
+    let image = match read_pixels(surface) {
+        Some(image) => image,
+        None => {
+            error!("Could not read the window contents for a screenshot");
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        let path = output_path(request.path, "png");
+        let image = prepare_image(image, region, request.scale);
+        match image.save(&path) {
+            Ok(()) => info!("Saved screenshot to {}", path.display()),
+            Err(error) => error!("Could not save screenshot to {}: {}", path.display(), error),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directories_get_a_file_name() {
+        let directory = std::env::temp_dir();
+        let path = output_path(directory.clone(), "png");
+        assert_eq!(path.parent(), Some(directory.as_path()));
+        assert_eq!(path.extension().unwrap(), "png");
+
+        assert_eq!(
+            output_path(directory.join("shot"), "png"),
+            directory.join("shot.png")
+        );
+        assert_eq!(
+            output_path(directory.join("shot.jpg"), "png"),
+            directory.join("shot.jpg")
+        );
+    }
+
+    #[test]
+    fn images_are_cropped_before_scaling() {
+        let image = RgbaImage::new(100, 50);
+
+        let cropped = prepare_image(
+            image.clone(),
+            Some(Rect::from_xywh(10.0, 5.0, 40.0, 20.0)),
+            1.0,
+        );
+        assert_eq!(cropped.dimensions(), (40, 20));
+
+        let scaled = prepare_image(
+            image.clone(),
+            Some(Rect::from_xywh(10.0, 5.0, 40.0, 20.0)),
+            2.0,
+        );
+        assert_eq!(scaled.dimensions(), (80, 40));
+
+        // Regions hanging off the frame are cut to what was drawn
+        let clamped = prepare_image(image, Some(Rect::from_xywh(80.0, 40.0, 40.0, 20.0)), 0.5);
+        assert_eq!(clamped.dimensions(), (10, 5));
+    }
+}
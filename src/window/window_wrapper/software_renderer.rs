@@ -0,0 +1,197 @@
+use glutin::window::Window;
+use skia_safe::{AlphaType, ColorType, ImageInfo, Surface};
+
+// Skia draws into memory in the byte order both X11 and Windows bitmaps expect, so frames can be
+// handed to the window system without conversion
+pub fn create_raster_surface((width, height): (u32, u32)) -> Surface {
+    let image_info = ImageInfo::new(
+        (width.max(1) as i32, height.max(1) as i32),
+        ColorType::BGRA8888,
+        AlphaType::Premul,
+        None,
+    );
+    Surface::new_raster(&image_info, None, None).expect("Could not create raster surface")
+}
+
+// Renders on the cpu and copies the finished frames into the window
+pub struct SoftwareRenderer {
+    surface: Surface,
+    presenter: Presenter,
+}
+
+impl SoftwareRenderer {
+    pub fn new(window: &Window) -> Result<SoftwareRenderer, String> {
+        let presenter = Presenter::new(window)?;
+        let size = window.inner_size();
+        let surface = create_raster_surface((size.width, size.height));
+
+        Ok(SoftwareRenderer { surface, presenter })
+    }
+
+    pub fn surface(&mut self) -> &mut Surface {
+        &mut self.surface
+    }
+
+    pub fn resize(&mut self, window: &Window) {
+        let size = window.inner_size();
+        self.surface = create_raster_surface((size.width, size.height));
+    }
+
+    pub fn present(&mut self) {
+        let pixmap = match self.surface.peek_pixels() {
+            Some(pixmap) => pixmap,
+            None => return,
+        };
+        let size = (pixmap.width(), pixmap.height());
+        self.presenter
+            .present(pixmap.bytes().unwrap_or_default(), size, pixmap.row_bytes());
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct Presenter {
+    xlib: x11_dl::xlib::Xlib,
+    display: *mut x11_dl::xlib::Display,
+    window: std::os::raw::c_ulong,
+    gc: x11_dl::xlib::GC,
+    // The window has a see through visual of its own, so images take its visual and depth
+    // rather than the screen's defaults
+    visual: *mut x11_dl::xlib::Visual,
+    depth: i32,
+}
+
+#[cfg(target_os = "linux")]
+impl Presenter {
+    fn new(window: &Window) -> Result<Presenter, String> {
+        use glutin::platform::unix::WindowExtUnix;
+        use x11_dl::xlib::Xlib;
+
+        let (display, window) = match (window.xlib_display(), window.xlib_window()) {
+            (Some(display), Some(window)) => (display as *mut x11_dl::xlib::Display, window),
+            _ => return Err("Software rendering in a window needs X11".to_owned()),
+        };
+        let xlib = Xlib::open().map_err(|error| error.to_string())?;
+        let mut attributes: x11_dl::xlib::XWindowAttributes = unsafe { std::mem::zeroed() };
+        if unsafe { (xlib.XGetWindowAttributes)(display, window, &mut attributes) } == 0 {
+            return Err("Could not read the attributes of the window".to_owned());
+        }
+        let gc = unsafe { (xlib.XCreateGC)(display, window, 0, std::ptr::null_mut()) };
+
+        Ok(Presenter {
+            xlib,
+            display,
+            window,
+            gc,
+            visual: attributes.visual,
+            depth: attributes.depth,
+        })
+    }
+
+    fn present(&mut self, pixels: &[u8], (width, height): (i32, i32), row_bytes: usize) {
+        unsafe {
+            let image = (self.xlib.XCreateImage)(
+                self.display,
+                self.visual,
+                self.depth as u32,
+                x11_dl::xlib::ZPixmap,
+                0,
+                pixels.as_ptr() as *mut _,
+                width as u32,
+                height as u32,
+                32,
+                row_bytes as i32,
+            );
+            if image.is_null() {
+                return;
+            }
+
+            (self.xlib.XPutImage)(
+                self.display,
+                self.window,
+                self.gc,
+                image,
+                0,
+                0,
+                0,
+                0,
+                width as u32,
+                height as u32,
+            );
+            // The pixels belong to the surface, so only the image itself is freed
+            (*image).data = std::ptr::null_mut();
+            (self.xlib.XFree)(image as *mut _);
+            (self.xlib.XFlush)(self.display);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Presenter {
+    fn drop(&mut self) {
+        unsafe { (self.xlib.XFreeGC)(self.display, self.gc) };
+    }
+}
+
+#[cfg(windows)]
+struct Presenter {
+    hwnd: winapi::shared::windef::HWND,
+}
+
+#[cfg(windows)]
+impl Presenter {
+    fn new(window: &Window) -> Result<Presenter, String> {
+        use glutin::platform::windows::WindowExtWindows;
+
+        Ok(Presenter {
+            hwnd: window.hwnd() as _,
+        })
+    }
+
+    fn present(&mut self, pixels: &[u8], (width, height): (i32, i32), row_bytes: usize) {
+        use winapi::um::wingdi::{
+            SetDIBitsToDevice, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
+        };
+        use winapi::um::winuser::{GetDC, ReleaseDC};
+
+        unsafe {
+            let mut info: BITMAPINFO = std::mem::zeroed();
+            info.bmiHeader.biSize = std::mem::size_of::<BITMAPINFOHEADER>() as u32;
+            info.bmiHeader.biWidth = (row_bytes / 4) as i32;
+            // Negative heights make the bitmap top down like the surface
+            info.bmiHeader.biHeight = -height;
+            info.bmiHeader.biPlanes = 1;
+            info.bmiHeader.biBitCount = 32;
+            info.bmiHeader.biCompression = BI_RGB;
+
+            let dc = GetDC(self.hwnd);
+            SetDIBitsToDevice(
+                dc,
+                0,
+                0,
+                width as u32,
+                height as u32,
+                0,
+                0,
+                0,
+                height as u32,
+                pixels.as_ptr() as *const _,
+                &info,
+                DIB_RGB_COLORS,
+            );
+            ReleaseDC(self.hwnd, dc);
+        }
+    }
+}
+
+// macOS always has OpenGL, so only offscreen rendering is supported on the cpu there
+#[cfg(not(any(target_os = "linux", windows)))]
+struct Presenter;
+
+#[cfg(not(any(target_os = "linux", windows)))]
+impl Presenter {
+    fn new(_window: &Window) -> Result<Presenter, String> {
+        Err("Software rendering in a window is not supported on this platform".to_owned())
+    }
+
+    fn present(&mut self, _pixels: &[u8], _size: (i32, i32), _row_bytes: usize) {}
+}